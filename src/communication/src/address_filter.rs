@@ -0,0 +1,41 @@
+//! drops frames meant for other nodes before they take a slot in the receive channel
+use crate::medium::{link_local_ipv6, solicited_node_mac, MacAddress, BROADCAST_MAC};
+
+const IPV6_ALL_NODES_MAC: MacAddress = [0x33, 0x33, 0x00, 0x00, 0x00, 0x01];
+
+#[derive(Clone, Copy)]
+pub struct AddressFilter {
+    /// multicast groups to accept besides broadcast
+    pub groups: &'static [MacAddress],
+    /// accept the ipv6 all nodes group and the solicited node group of the link local
+    /// address, neighbor discovery needs both
+    pub ipv6_neighbor_discovery: bool,
+}
+
+impl Default for AddressFilter {
+    fn default() -> Self {
+        Self {
+            groups: &[],
+            ipv6_neighbor_discovery: true,
+        }
+    }
+}
+
+impl AddressFilter {
+    /// true if a frame with this destination is for the node with `address`
+    pub fn accepts(&self, address: MacAddress, destination: MacAddress) -> bool {
+        if destination == address || destination == BROADCAST_MAC {
+            return true;
+        }
+        if destination[0] & 0x01 == 0 {
+            return false;
+        }
+        if self.ipv6_neighbor_discovery {
+            let solicited_node = solicited_node_mac(link_local_ipv6(address));
+            if destination == IPV6_ALL_NODES_MAC || destination == solicited_node {
+                return true;
+            }
+        }
+        self.groups.contains(&destination)
+    }
+}
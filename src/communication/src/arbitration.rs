@@ -0,0 +1,70 @@
+//! priority arbitration in the spirit of CAN: every burst starts with an arbitration field
+//! that stands for `[priority][node id]`, and the transmitter compares the echo of the field
+//! one byte at a time. A node that reads back something else lost against a more urgent
+//! frame, it stops right away and tries again once the line is free.
+//!
+//! This needs a line on which a zero bit wins over a one bit, e.g. an RS-485 bus on which
+//! the drivers are only enabled for zeros, so bytes sent at the same time come back as their
+//! bitwise and. A uart only sees the echo of whole bytes, so the field cannot hold the plain
+//! values: every byte carries one base 8 digit as a thermometer code, digit `d` is the
+//! `d + 1` lowest bits set. The and of two such bytes is the smaller digit, the node that
+//! sent it reads back its own byte and the other one sees the difference. The digits go out
+//! most significant first, so lower values win, and the node id makes every field unique
+//! so that two frames never tie. The field never holds a zero byte, so it can go in front
+//! of a cobs encoded frame as it is
+use crate::medium::{node_id, MacAddress, BROADCAST_NODE};
+
+/// base 8 digits of a byte
+const DIGITS: usize = 3;
+pub const ARBITRATION_FIELD_SIZE: usize = 2 * DIGITS;
+/// priority of frames the classifier does not care about
+pub const DEFAULT_PRIORITY: u8 = 0x80;
+
+#[derive(Clone, Copy)]
+pub struct ArbitrationConfig {
+    /// priority of a frame from the stack, lower values win the bus. Every node on the bus
+    /// must use arbitration
+    pub classify: fn(&[u8]) -> u8,
+}
+
+impl Default for ArbitrationConfig {
+    fn default() -> Self {
+        Self {
+            classify: |_| DEFAULT_PRIORITY,
+        }
+    }
+}
+
+/// writes `value` as thermometer coded base 8 digits, most significant first
+fn encode(value: u8, out: &mut [u8]) {
+    let mut rest = value as u16;
+    for digit in out[..DIGITS].iter_mut().rev() {
+        *digit = ((2u16 << (rest % 8)) - 1) as u8;
+        rest /= 8;
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct Arbiter {
+    classify: fn(&[u8]) -> u8,
+    id: u8,
+}
+
+impl Arbiter {
+    pub fn new(config: ArbitrationConfig, address: MacAddress) -> Self {
+        Self {
+            classify: config.classify,
+            id: node_id(address)
+                .filter(|&id| id != BROADCAST_NODE)
+                .expect("arbitration needs a node mac address"),
+        }
+    }
+
+    /// the arbitration field for a frame from the stack
+    pub fn field(&self, frame: &[u8]) -> [u8; ARBITRATION_FIELD_SIZE] {
+        let mut field = [0; ARBITRATION_FIELD_SIZE];
+        encode((self.classify)(frame), &mut field[..DIGITS]);
+        encode(self.id, &mut field[DIGITS..]);
+        field
+    }
+}
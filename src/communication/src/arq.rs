@@ -0,0 +1,216 @@
+//! link layer stop-and-wait ARQ. Unicast frames carry a sequence number and are
+//! repeated through the backoff path until the destination acknowledges them,
+//! broadcast and multicast frames are sent once.
+//!
+//! with ARQ enabled every frame on the bus starts with a kind byte:
+//! `[DATA][frame]`, `[DATA_ARQ][seq][frame]` or `[ACK][seq][acked sender][acking node]`.
+//! With fragmentation every acknowledgement goes out as a fragment of its own
+use crate::medium::MacAddress;
+
+use core::cell::Cell;
+use core::ops::Range;
+
+use defmt::*;
+use embassy_time::{Duration, Instant};
+
+const KIND_DATA: u8 = 0x00;
+const KIND_DATA_ARQ: u8 = 0x01;
+const KIND_ACK: u8 = 0x02;
+
+/// largest header ARQ puts in front of a frame
+pub const ARQ_HEADER_SIZE: usize = 2;
+pub(crate) const ACK_LEN: usize = 2 + 6 + 6;
+const DUPLICATE_TABLE_SIZE: usize = 8;
+
+/// acknowledgements are only sent for frames addressed to the address of the node,
+/// see [`HalfDuplexConfig::address`](crate::half_duplex::HalfDuplexConfig::address)
+pub struct ArqConfig {
+    /// how long to wait for an acknowledgement before the frame is repeated
+    pub ack_timeout: Duration,
+}
+
+impl Default for ArqConfig {
+    fn default() -> Self {
+        Self {
+            ack_timeout: Duration::from_millis(5),
+        }
+    }
+}
+
+/// reads destination and source from a frame as it is on the bus, `None` if the
+/// frame carries no addresses
+pub(crate) type AddressParser = fn(&[u8]) -> Option<(MacAddress, MacAddress)>;
+
+/// handed between the receive and transmit halves of the driver
+#[derive(Default)]
+pub(crate) struct ArqShared {
+    /// an acknowledgement the receiver wants the transmitter to send
+    pending_ack: Cell<Option<(u8, MacAddress)>>,
+    /// the last acknowledgement the receiver saw for us
+    acked: Cell<Option<(u8, MacAddress)>>,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct AwaitingAck {
+    pub seq: u8,
+    pub to: MacAddress,
+    pub deadline: Instant,
+}
+
+pub(crate) struct ArqSender {
+    config: ArqConfig,
+    address: MacAddress,
+    next_seq: u8,
+    /// sequence number and destination of the frame at the head of the queue, kept for retransmissions
+    current: Option<(u8, MacAddress)>,
+    pub awaiting: Option<AwaitingAck>,
+}
+
+impl ArqSender {
+    pub fn new(config: ArqConfig, address: MacAddress) -> Self {
+        Self {
+            config,
+            address,
+            next_seq: 0,
+            current: None,
+            awaiting: None,
+        }
+    }
+
+    /// writes the arq header in front of the frame that starts at `start` in `buf`,
+    /// returns where the header starts. `destination` is `None` for group addresses
+    pub fn prepend_header(
+        &mut self,
+        buf: &mut [u8],
+        start: usize,
+        destination: Option<MacAddress>,
+    ) -> usize {
+        let Some(destination) = destination else {
+            buf[start - 1] = KIND_DATA;
+            return start - 1;
+        };
+        let seq = match self.current {
+            Some((seq, _)) => seq,
+            None => {
+                let seq = self.next_seq;
+                self.next_seq = self.next_seq.wrapping_add(1);
+                self.current = Some((seq, destination));
+                seq
+            }
+        };
+        buf[start - 2] = KIND_DATA_ARQ;
+        buf[start - 1] = seq;
+        start - 2
+    }
+
+    /// called after the frame was put on the bus, returns true if an acknowledgement is expected
+    pub fn on_sent(&mut self) -> bool {
+        let Some((seq, to)) = self.current else {
+            return false;
+        };
+        self.awaiting = Some(AwaitingAck {
+            seq,
+            to,
+            deadline: Instant::now() + self.config.ack_timeout,
+        });
+        true
+    }
+
+    pub fn is_acked(&self, awaiting: &AwaitingAck, shared: &ArqShared) -> bool {
+        shared.acked.get() == Some((awaiting.seq, awaiting.to))
+    }
+
+    /// acknowledgement the transmitter should send next, if any
+    pub fn pending_ack(&self, shared: &ArqShared) -> Option<[u8; ACK_LEN]> {
+        let (seq, to) = shared.pending_ack.get()?;
+        let mut frame = [0; ACK_LEN];
+        frame[0] = KIND_ACK;
+        frame[1] = seq;
+        frame[2..8].copy_from_slice(&to);
+        frame[8..14].copy_from_slice(&self.address);
+        Some(frame)
+    }
+
+    pub fn ack_sent(&self, shared: &ArqShared) {
+        shared.pending_ack.set(None);
+    }
+
+    /// the frame at the head of the queue is done, delivered or not
+    pub fn finish(&mut self) {
+        self.current = None;
+        self.awaiting = None;
+    }
+}
+
+pub(crate) struct ArqReceiver {
+    address: MacAddress,
+    addresses: AddressParser,
+    /// last sequence number seen per sender
+    seen: [Option<(MacAddress, u8)>; DUPLICATE_TABLE_SIZE],
+    next_slot: usize,
+}
+
+pub(crate) enum Received {
+    /// part of the bus frame that holds the frame for the stack
+    Deliver(Range<usize>),
+    /// a repetition of a frame that was already delivered
+    Duplicate,
+    /// a link layer frame that was handled here
+    Consumed,
+    Malformed,
+}
+
+impl ArqReceiver {
+    pub fn new(address: MacAddress, addresses: AddressParser) -> Self {
+        Self {
+            address,
+            addresses,
+            seen: [None; DUPLICATE_TABLE_SIZE],
+            next_slot: 0,
+        }
+    }
+
+    fn is_duplicate(&mut self, from: MacAddress, seq: u8) -> bool {
+        for entry in self.seen.iter_mut().flatten() {
+            if entry.0 == from {
+                let duplicate = entry.1 == seq;
+                entry.1 = seq;
+                return duplicate;
+            }
+        }
+        self.seen[self.next_slot] = Some((from, seq));
+        self.next_slot = (self.next_slot + 1) % DUPLICATE_TABLE_SIZE;
+        false
+    }
+
+    pub fn receive(&mut self, frame: &[u8], shared: &ArqShared) -> Received {
+        match frame.first() {
+            Some(&KIND_DATA) => Received::Deliver(1..frame.len()),
+            Some(&KIND_DATA_ARQ) if frame.len() >= 2 => {
+                let seq = frame[1];
+                let Some((destination, from)) = (self.addresses)(&frame[2..]) else {
+                    return Received::Malformed;
+                };
+                if destination != self.address {
+                    return Received::Deliver(2..frame.len());
+                }
+                // acknowledge repetitions as well, our previous ack may have been lost
+                shared.pending_ack.set(Some((seq, from)));
+                if self.is_duplicate(from, seq) {
+                    info!("duplicate frame {}", seq);
+                    return Received::Duplicate;
+                }
+                Received::Deliver(2..frame.len())
+            }
+            Some(&KIND_ACK) if frame.len() == ACK_LEN => {
+                if frame[2..8] == self.address {
+                    let mut from = [0; 6];
+                    from.copy_from_slice(&frame[8..14]);
+                    shared.acked.set(Some((frame[1], from)));
+                }
+                Received::Consumed
+            }
+            _ => Received::Malformed,
+        }
+    }
+}
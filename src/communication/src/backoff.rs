@@ -0,0 +1,174 @@
+use embassy_time::Duration;
+use rand_core::RngCore;
+
+/// decides how long a node stays off the bus after a failed attempt,
+/// and after how many attempts a frame is given up
+pub trait BackoffPolicy {
+    /// number of attempts after which the frame is abandoned
+    fn max_backoffs(&self) -> usize;
+
+    /// time to wait after `attempt` failed attempts, `attempt` starts at 1
+    fn backoff_duration<R: RngCore>(&mut self, attempt: usize, rng: &mut R) -> Duration;
+}
+
+fn random_below<R: RngCore>(rng: &mut R, bound: u32) -> u32 {
+    if bound == 0 {
+        return 0;
+    }
+    rng.next_u32() % bound
+}
+
+fn slots(slot: Duration, count: u32) -> Duration {
+    Duration::from_micros(slot.as_micros() * count as u64)
+}
+
+/// the default policy and the curve the driver always had: after the n-th failed attempt
+/// wait `base << n` plus up to 255 µs of random jitter
+pub struct BinaryExponential {
+    pub base: Duration,
+    pub max_backoffs: usize,
+}
+
+impl Default for BinaryExponential {
+    fn default() -> Self {
+        Self {
+            base: Duration::from_millis(1),
+            max_backoffs: 5,
+        }
+    }
+}
+
+impl BackoffPolicy for BinaryExponential {
+    fn max_backoffs(&self) -> usize {
+        self.max_backoffs
+    }
+
+    fn backoff_duration<R: RngCore>(&mut self, attempt: usize, rng: &mut R) -> Duration {
+        let exponent = core::cmp::min(attempt as u32, 31);
+        let jitter = rng.next_u32() as u8;
+        Duration::from_micros((self.base.as_micros() << exponent) + jitter as u64)
+    }
+}
+
+/// classic ethernet backoff: after the n-th collision wait a random number of slots
+/// in `[0, 2^min(n, ceiling))`. The window stops growing at `ceiling`
+pub struct TruncatedBinaryExponential {
+    pub slot: Duration,
+    pub ceiling: u32,
+    pub max_backoffs: usize,
+}
+
+impl Default for TruncatedBinaryExponential {
+    fn default() -> Self {
+        Self {
+            slot: Duration::from_millis(1),
+            ceiling: 10,
+            max_backoffs: 5,
+        }
+    }
+}
+
+impl BackoffPolicy for TruncatedBinaryExponential {
+    fn max_backoffs(&self) -> usize {
+        self.max_backoffs
+    }
+
+    fn backoff_duration<R: RngCore>(&mut self, attempt: usize, rng: &mut R) -> Duration {
+        let exponent = core::cmp::min(attempt as u32, core::cmp::min(self.ceiling, 31));
+        let window = 1u32 << exponent;
+        slots(self.slot, random_below(rng, window))
+    }
+}
+
+/// waits `step * attempt` plus up to `jitter` of random delay
+pub struct Linear {
+    pub step: Duration,
+    pub jitter: Duration,
+    pub max_backoffs: usize,
+}
+
+impl Default for Linear {
+    fn default() -> Self {
+        Self {
+            step: Duration::from_millis(1),
+            jitter: Duration::from_micros(500),
+            max_backoffs: 8,
+        }
+    }
+}
+
+impl BackoffPolicy for Linear {
+    fn max_backoffs(&self) -> usize {
+        self.max_backoffs
+    }
+
+    fn backoff_duration<R: RngCore>(&mut self, attempt: usize, rng: &mut R) -> Duration {
+        let jitter = random_below(rng, self.jitter.as_micros() as u32);
+        Duration::from_micros(self.step.as_micros() * attempt as u64 + jitter as u64)
+    }
+}
+
+/// picks a random slot out of a contention window that does not grow with the attempts
+pub struct FixedSlot {
+    pub slot: Duration,
+    pub window: u32,
+    pub max_backoffs: usize,
+}
+
+impl Default for FixedSlot {
+    fn default() -> Self {
+        Self {
+            slot: Duration::from_millis(1),
+            window: 16,
+            max_backoffs: 8,
+        }
+    }
+}
+
+impl BackoffPolicy for FixedSlot {
+    fn max_backoffs(&self) -> usize {
+        self.max_backoffs
+    }
+
+    fn backoff_duration<R: RngCore>(&mut self, _attempt: usize, rng: &mut R) -> Duration {
+        // always wait at least one slot so that the node that lost does not retry right away
+        slots(self.slot, 1 + random_below(rng, self.window))
+    }
+}
+
+/// p-persistent access: every slot the node transmits with probability `p_percent` / 100,
+/// otherwise it defers to the next slot. The backoff is the number of deferred slots
+pub struct PPersistent {
+    pub slot: Duration,
+    pub p_percent: u8,
+    pub max_backoffs: usize,
+}
+
+impl PPersistent {
+    const MAX_DEFERRED_SLOTS: u32 = 64;
+}
+
+impl Default for PPersistent {
+    fn default() -> Self {
+        Self {
+            slot: Duration::from_millis(1),
+            p_percent: 25,
+            max_backoffs: 8,
+        }
+    }
+}
+
+impl BackoffPolicy for PPersistent {
+    fn max_backoffs(&self) -> usize {
+        self.max_backoffs
+    }
+
+    fn backoff_duration<R: RngCore>(&mut self, _attempt: usize, rng: &mut R) -> Duration {
+        let p = core::cmp::max(self.p_percent, 1) as u32;
+        let mut deferred = 1;
+        while deferred < Self::MAX_DEFERRED_SLOTS && random_below(rng, 100) >= p {
+            deferred += 1;
+        }
+        slots(self.slot, deferred)
+    }
+}
@@ -0,0 +1,73 @@
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use embassy_time::{Duration, Instant};
+
+/// receive activity of a shared line, written by the receiving half and
+/// read by the transmitting half to decide whether the line is free.
+///
+/// The time of the last idle is kept as the lower 32 bits of the tick counter,
+/// elapsed times are computed with wrapping arithmetic so this works on targets
+/// without 64 bit atomics
+pub struct LineActivity {
+    receiving: AtomicBool,
+    last_idle: AtomicU32,
+    inter_frame_gap: Duration,
+}
+
+impl LineActivity {
+    pub const fn new(inter_frame_gap: Duration) -> Self {
+        Self {
+            receiving: AtomicBool::new(false),
+            last_idle: AtomicU32::new(0),
+            inter_frame_gap,
+        }
+    }
+
+    fn now_ticks() -> u32 {
+        Instant::now().as_ticks() as u32
+    }
+
+    /// start of reception edge: someone is putting a frame on the line
+    pub fn on_rx_start(&self) {
+        self.receiving.store(true, Ordering::SeqCst);
+    }
+
+    /// the line went idle after a frame
+    pub fn on_rx_idle(&self) {
+        self.last_idle.store(Self::now_ticks(), Ordering::SeqCst);
+        self.receiving.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_receiving(&self) -> bool {
+        self.receiving.load(Ordering::SeqCst)
+    }
+
+    pub fn inter_frame_gap(&self) -> Duration {
+        self.inter_frame_gap
+    }
+
+    /// time since the line last went idle
+    pub fn quiet_for(&self) -> Duration {
+        let elapsed = Self::now_ticks().wrapping_sub(self.last_idle.load(Ordering::SeqCst));
+        Duration::from_ticks(elapsed as u64)
+    }
+
+    /// the line is free when nothing is being received and it has been quiet
+    /// for at least the inter frame gap
+    pub fn is_line_free(&self) -> bool {
+        !self.is_receiving() && self.quiet_for() >= self.inter_frame_gap
+    }
+
+    /// how long until the line can be free at the earliest. While a frame is
+    /// being received this is a whole inter frame gap
+    pub fn time_until_free(&self) -> Duration {
+        if self.is_receiving() {
+            return self.inter_frame_gap;
+        }
+        let quiet = self.quiet_for();
+        if quiet >= self.inter_frame_gap {
+            return Duration::from_ticks(0);
+        }
+        self.inter_frame_gap - quiet
+    }
+}
@@ -0,0 +1,329 @@
+//! consistent overhead byte stuffing: encoded frames never contain a zero byte, so a zero
+//! can delimit frames on the wire independently of the idle line interrupt.
+use crate::arbitration::ARBITRATION_FIELD_SIZE;
+use crate::half_duplex::BUS_FRAME_SIZE;
+use crate::{Read, ReadError, Write, WriteError};
+
+use defmt::*;
+use embassy_time::Duration;
+
+const DELIMITER: u8 = 0;
+
+/// worst case size of `len` bytes after encoding, without delimiters
+pub const fn max_encoded_len(len: usize) -> usize {
+    len + len / 254 + 1
+}
+
+/// buffer size that fits one full bus frame plus both delimiters
+pub const COBS_BUFFER_SIZE: usize = max_encoded_len(BUS_FRAME_SIZE) + 2;
+
+/// encodes `src` into `dst`, returns the number of bytes written or `None` if `dst` is too small
+pub fn encode(src: &[u8], dst: &mut [u8]) -> Option<usize> {
+    if dst.len() < max_encoded_len(src.len()) {
+        return None;
+    }
+    let mut code_index = 0;
+    let mut out = 1;
+    let mut code: u8 = 1;
+    for &byte in src {
+        if byte == DELIMITER {
+            dst[code_index] = code;
+            code_index = out;
+            out += 1;
+            code = 1;
+        } else {
+            dst[out] = byte;
+            out += 1;
+            code += 1;
+            if code == 0xFF {
+                dst[code_index] = code;
+                code_index = out;
+                out += 1;
+                code = 1;
+            }
+        }
+    }
+    dst[code_index] = code;
+    Some(out)
+}
+
+/// decodes a frame without delimiters, returns `None` if it is malformed or does not fit `dst`
+pub fn decode(src: &[u8], dst: &mut [u8]) -> Option<usize> {
+    let mut read = 0;
+    let mut out = 0;
+    while read < src.len() {
+        let code = src[read];
+        if code == DELIMITER {
+            return None;
+        }
+        read += 1;
+        let run = code as usize - 1;
+        if read + run > src.len() || out + run > dst.len() {
+            return None;
+        }
+        for i in 0..run {
+            let byte = src[read + i];
+            if byte == DELIMITER {
+                return None;
+            }
+            dst[out + i] = byte;
+        }
+        read += run;
+        out += run;
+        // a block shorter than 254 bytes stands for a zero, unless it ends the frame
+        if code != 0xFF && read < src.len() {
+            if out >= dst.len() {
+                return None;
+            }
+            dst[out] = 0;
+            out += 1;
+        }
+    }
+    Some(out)
+}
+
+/// writes every frame cobs encoded between two zero bytes. The leading zero
+/// terminates whatever garbage a receiver may still hold from a broken frame
+pub struct CobsWriter<W: Write, const N: usize = COBS_BUFFER_SIZE> {
+    write: W,
+    buf: [u8; N],
+}
+
+impl<W: Write, const N: usize> CobsWriter<W, N> {
+    pub fn new(write: W) -> Self {
+        Self { write, buf: [0; N] }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.write
+    }
+
+    /// puts `[0][raw][encoded][0]` in the buffer, the first `raw` bytes of `buf` are not
+    /// encoded and must not hold a zero. Returns the length
+    fn frame(&mut self, buf: &[u8], raw: usize) -> Result<usize, WriteError> {
+        if N < raw + 2 || buf.len() < raw || buf[..raw].contains(&DELIMITER) {
+            return Err(WriteError::FramingError);
+        }
+        self.buf[0] = DELIMITER;
+        self.buf[1..raw + 1].copy_from_slice(&buf[..raw]);
+        let Some(len) = encode(&buf[raw..], &mut self.buf[raw + 1..N - 1]) else {
+            info!("frame of {} bytes does not fit the cobs buffer", buf.len());
+            return Err(WriteError::FramingError);
+        };
+        let end = raw + 1 + len;
+        self.buf[end] = DELIMITER;
+        Ok(end + 1)
+    }
+}
+
+impl<W: Write, const N: usize> Write for CobsWriter<W, N> {
+    async fn write<'a>(&'a mut self, buf: &'a [u8]) -> Result<(), WriteError>
+    where
+        Self: Sized,
+    {
+        let len = self.frame(buf, 0)?;
+        self.write.write(&self.buf[..len]).await
+    }
+
+    /// the arbitration field goes out as it is, right after the leading delimiter, so
+    /// that every node compares the same bytes. See [`CobsReader::with_arbitration`]
+    async fn write_arbitrated<'a>(
+        &'a mut self,
+        buf: &'a [u8],
+        arbitration: usize,
+    ) -> Result<(), WriteError>
+    where
+        Self: Sized,
+    {
+        let len = self.frame(buf, arbitration)?;
+        // the leading delimiter is the same on every node
+        self.write
+            .write_arbitrated(&self.buf[..len], arbitration + 1)
+            .await
+    }
+
+    async fn write_raw<'a>(&'a mut self, buf: &'a [u8]) -> Result<(), WriteError>
+    where
+        Self: Sized,
+    {
+        self.write.write_raw(buf).await
+    }
+
+    fn is_line_free(&self) -> bool {
+        self.write.is_line_free()
+    }
+
+    fn time_until_free(&self) -> Duration {
+        self.write.time_until_free()
+    }
+}
+
+/// splits the byte stream of the wrapped reader at zero bytes, no matter how the
+/// idle line interrupt cut it up. After an error everything up to the next zero is dropped
+pub struct CobsReader<R: Read, const N: usize = COBS_BUFFER_SIZE> {
+    read: R,
+    buf: [u8; N],
+    start: usize,
+    end: usize,
+    discarding: bool,
+    /// bytes at the start of every frame that are not encoded
+    raw: usize,
+}
+
+impl<R: Read, const N: usize> CobsReader<R, N> {
+    pub fn new(read: R) -> Self {
+        Self {
+            read,
+            buf: [0; N],
+            start: 0,
+            end: 0,
+            discarding: false,
+            raw: 0,
+        }
+    }
+
+    /// every frame starts with an arbitration field that was not encoded, for a link with
+    /// [`crate::arbitration`] on a [`CobsWriter`]
+    pub fn with_arbitration(mut self) -> Self {
+        self.raw = ARBITRATION_FIELD_SIZE;
+        self
+    }
+
+    pub fn into_inner(self) -> R {
+        self.read
+    }
+
+    /// returns the next complete frame that is already buffered, without its delimiter
+    fn next_buffered(&mut self) -> Option<(usize, usize)> {
+        let offset = self.buf[self.start..self.end]
+            .iter()
+            .position(|&b| b == DELIMITER)?;
+        let frame = (self.start, self.start + offset);
+        self.start += offset + 1;
+        Some(frame)
+    }
+
+    fn compact(&mut self) {
+        self.buf.copy_within(self.start..self.end, 0);
+        self.end -= self.start;
+        self.start = 0;
+    }
+
+    /// drop the frame in progress, the stream resynchronises on the next zero
+    fn resync(&mut self) {
+        self.start = 0;
+        self.end = 0;
+        self.discarding = true;
+    }
+}
+
+impl<R: Read, const N: usize> Read for CobsReader<R, N> {
+    async fn read_until_idle<'a>(&'a mut self, buf: &'a mut [u8]) -> Result<usize, ReadError>
+    where
+        Self: Sized,
+    {
+        loop {
+            while let Some((start, end)) = self.next_buffered() {
+                if self.discarding {
+                    // the tail of a broken frame
+                    self.discarding = false;
+                    continue;
+                }
+                if start == end {
+                    // back to back delimiters
+                    continue;
+                }
+                let raw = self.raw;
+                if end - start < raw || buf.len() < raw {
+                    info!("cobs frame shorter than its raw prefix");
+                    return Err(ReadError::FramingError);
+                }
+                buf[..raw].copy_from_slice(&self.buf[start..start + raw]);
+                return match decode(&self.buf[start + raw..end], &mut buf[raw..]) {
+                    Some(len) => Ok(raw + len),
+                    None => {
+                        info!("dropping malformed cobs frame");
+                        Err(ReadError::FramingError)
+                    }
+                };
+            }
+
+            self.compact();
+            if self.end == N {
+                info!("no delimiter in {} bytes, resynchronising", N);
+                self.resync();
+                return Err(ReadError::OverflowError);
+            }
+            match self.read.read_until_idle(&mut self.buf[self.end..]).await {
+                Ok(read) => self.end += read,
+                Err(err) => {
+                    self.resync();
+                    return Err(err);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(frame: &[u8]) {
+        let mut encoded = [0u8; 1100];
+        let len = encode(frame, &mut encoded).unwrap();
+        assert!(len <= max_encoded_len(frame.len()));
+        assert!(!encoded[..len].contains(&DELIMITER));
+        let mut decoded = [0u8; 1100];
+        let decoded_len = decode(&encoded[..len], &mut decoded).unwrap();
+        assert_eq!(&decoded[..decoded_len], frame);
+    }
+
+    #[test]
+    fn empty_frame() {
+        round_trip(&[]);
+    }
+
+    #[test]
+    fn zero_runs() {
+        round_trip(&[0]);
+        round_trip(&[0, 0, 0]);
+        round_trip(&[1, 0, 0, 2, 0]);
+        round_trip(&[0; 300]);
+    }
+
+    #[test]
+    fn runs_around_the_block_size() {
+        for len in [253, 254, 255, 508, 1000] {
+            let mut frame = [0u8; 1000];
+            for (i, byte) in frame[..len].iter_mut().enumerate() {
+                *byte = (i % 255) as u8 + 1;
+            }
+            round_trip(&frame[..len]);
+            frame[len - 1] = 0;
+            round_trip(&frame[..len]);
+            frame[0] = 0;
+            round_trip(&frame[..len]);
+        }
+    }
+
+    #[test]
+    fn known_encoding() {
+        let mut encoded = [0u8; 8];
+        let len = encode(&[0x11, 0x22, 0x00, 0x33], &mut encoded).unwrap();
+        assert_eq!(&encoded[..len], &[0x03, 0x11, 0x22, 0x02, 0x33]);
+    }
+
+    #[test]
+    fn rejects_delimiter_and_short_block() {
+        let mut decoded = [0u8; 8];
+        assert_eq!(decode(&[0x03, 0x11, 0x00], &mut decoded), None);
+        assert_eq!(decode(&[0x05, 0x11], &mut decoded), None);
+    }
+
+    #[test]
+    fn rejects_small_buffer() {
+        let mut encoded = [0u8; 3];
+        assert_eq!(encode(&[1, 2, 3], &mut encoded), None);
+    }
+}
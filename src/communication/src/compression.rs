@@ -0,0 +1,461 @@
+//! ipv4 and udp header compression in the spirit of 6LoWPAN IPHC.
+//!
+//! A compressed packet starts with a flags byte, the fields it marks as inline follow in
+//! header order: `[flags][tos][identification][ttl][protocol][source][destination]`.
+//! Udp packets continue with `[ports][source port][destination port][checksum]`, the ports
+//! byte holds an index into the well known ports for each port, [`INLINE_PORT`] if it is inline.
+//! Lengths and the header checksum are always recomputed by the receiver.
+use crate::medium::BROADCAST_NODE;
+
+use defmt::*;
+
+const IPV4_HEADER_SIZE: usize = 20;
+const UDP_HEADER_SIZE: usize = 8;
+pub(crate) const PROTOCOL_UDP: u8 = 17;
+const FLAG_DONT_FRAGMENT: u8 = 0x40;
+
+const TOS_INLINE: u8 = 0x01;
+const DONT_FRAGMENT: u8 = 0x02;
+const TTL_INLINE: u8 = 0x04;
+const NOT_UDP: u8 = 0x08;
+const SOURCE_INLINE: u8 = 0x10;
+const DESTINATION_INLINE: u8 = 0x20;
+
+/// the port is not in the well known ports and follows the ports byte
+const INLINE_PORT: u8 = 0x0F;
+
+/// link addresses of a packet as node ids, the last byte of the ip addresses they stand for
+#[derive(Clone, Copy)]
+pub(crate) struct LinkNodes {
+    pub destination: u8,
+    pub source: u8,
+}
+
+#[derive(Clone, Copy)]
+pub struct CompressionConfig {
+    /// the first three bytes of the ip address of every node, node `n` is `network.n`.
+    /// The broadcast node stands for `255.255.255.255`
+    pub network: [u8; 3],
+    /// the ttl the stack uses, other ttls are sent inline
+    pub ttl: u8,
+    /// ports that are sent as an index into this list, at most 15
+    pub well_known_ports: &'static [u16],
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            network: [192, 168, 69],
+            ttl: 64,
+            well_known_ports: &[9400],
+        }
+    }
+}
+
+impl CompressionConfig {
+    fn address_of(&self, node: u8) -> [u8; 4] {
+        if node == BROADCAST_NODE {
+            return [255; 4];
+        }
+        [self.network[0], self.network[1], self.network[2], node]
+    }
+
+    fn is_derived(&self, address: &[u8], node: Option<u8>) -> bool {
+        match node {
+            Some(node) => self.address_of(node) == *address,
+            None => false,
+        }
+    }
+
+    fn port_index(&self, port: u16) -> u8 {
+        match self.well_known_ports.iter().position(|&p| p == port) {
+            Some(index) if index < INLINE_PORT as usize => index as u8,
+            _ => INLINE_PORT,
+        }
+    }
+
+    fn port(&self, index: u8) -> Option<u16> {
+        self.well_known_ports.get(index as usize).copied()
+    }
+
+    /// compresses an ipv4 packet into `out`, `None` if it cannot be compressed or
+    /// would not get any shorter
+    pub(crate) fn compress(
+        &self,
+        packet: &[u8],
+        link: Option<LinkNodes>,
+        out: &mut [u8],
+    ) -> Option<usize> {
+        if packet.len() < IPV4_HEADER_SIZE || packet[0] != 0x45 {
+            return None;
+        }
+        let total_len = u16::from_be_bytes([packet[2], packet[3]]) as usize;
+        let fragmentation = u16::from_be_bytes([packet[6], packet[7]]);
+        let dont_fragment = fragmentation == (FLAG_DONT_FRAGMENT as u16) << 8;
+        if total_len != packet.len() || (fragmentation != 0 && !dont_fragment) {
+            return None;
+        }
+        let protocol = packet[9];
+        let is_udp = protocol == PROTOCOL_UDP && packet.len() >= IPV4_HEADER_SIZE + UDP_HEADER_SIZE;
+        // the udp length is left out, so it has to follow from the packet length
+        if is_udp {
+            let udp_len = u16::from_be_bytes([packet[24], packet[25]]) as usize;
+            if udp_len != total_len - IPV4_HEADER_SIZE {
+                return None;
+            }
+        }
+
+        let mut flags = 0;
+        let mut header = [0; IPV4_HEADER_SIZE + UDP_HEADER_SIZE + 1];
+        let mut len = 1;
+        let mut push = |bytes: &[u8]| {
+            header[len..len + bytes.len()].copy_from_slice(bytes);
+            len += bytes.len();
+        };
+
+        if packet[1] != 0 {
+            flags |= TOS_INLINE;
+            push(&packet[1..2]);
+        }
+        push(&packet[4..6]);
+        if dont_fragment {
+            flags |= DONT_FRAGMENT;
+        }
+        if packet[8] != self.ttl {
+            flags |= TTL_INLINE;
+            push(&packet[8..9]);
+        }
+        if !is_udp {
+            flags |= NOT_UDP;
+            push(&packet[9..10]);
+        }
+        let source = &packet[12..16];
+        if !self.is_derived(source, link.map(|link| link.source)) {
+            flags |= SOURCE_INLINE;
+            push(source);
+        }
+        let destination = &packet[16..20];
+        if !self.is_derived(destination, link.map(|link| link.destination)) {
+            flags |= DESTINATION_INLINE;
+            push(destination);
+        }
+
+        let mut rest = &packet[IPV4_HEADER_SIZE..];
+        if is_udp {
+            let source_port = u16::from_be_bytes([rest[0], rest[1]]);
+            let destination_port = u16::from_be_bytes([rest[2], rest[3]]);
+            let source_index = self.port_index(source_port);
+            let destination_index = self.port_index(destination_port);
+            push(&[source_index << 4 | destination_index]);
+            if source_index == INLINE_PORT {
+                push(&rest[0..2]);
+            }
+            if destination_index == INLINE_PORT {
+                push(&rest[2..4]);
+            }
+            // udp length follows from the packet length
+            push(&rest[6..8]);
+            rest = &rest[UDP_HEADER_SIZE..];
+        }
+        header[0] = flags;
+
+        if len + rest.len() >= packet.len() || len + rest.len() > out.len() {
+            return None;
+        }
+        out[..len].copy_from_slice(&header[..len]);
+        out[len..len + rest.len()].copy_from_slice(rest);
+        Some(len + rest.len())
+    }
+
+    /// restores the ipv4 packet from its compressed form, `None` if it is malformed
+    /// or does not fit `out`
+    pub(crate) fn decompress(
+        &self,
+        compressed: &[u8],
+        link: Option<LinkNodes>,
+        out: &mut [u8],
+    ) -> Option<usize> {
+        let flags = *compressed.first()?;
+        let mut cursor = Cursor {
+            bytes: compressed,
+            read: 1,
+        };
+        let mut header = [0; IPV4_HEADER_SIZE + UDP_HEADER_SIZE];
+        header[0] = 0x45;
+        if flags & TOS_INLINE != 0 {
+            header[1] = cursor.take(1)?[0];
+        }
+        header[4..6].copy_from_slice(cursor.take(2)?);
+        if flags & DONT_FRAGMENT != 0 {
+            header[6] = FLAG_DONT_FRAGMENT;
+        }
+        header[8] = if flags & TTL_INLINE != 0 {
+            cursor.take(1)?[0]
+        } else {
+            self.ttl
+        };
+        let is_udp = flags & NOT_UDP == 0;
+        header[9] = if is_udp {
+            PROTOCOL_UDP
+        } else {
+            cursor.take(1)?[0]
+        };
+        if flags & SOURCE_INLINE != 0 {
+            header[12..16].copy_from_slice(cursor.take(4)?);
+        } else {
+            header[12..16].copy_from_slice(&self.address_of(link?.source));
+        }
+        if flags & DESTINATION_INLINE != 0 {
+            header[16..20].copy_from_slice(cursor.take(4)?);
+        } else {
+            header[16..20].copy_from_slice(&self.address_of(link?.destination));
+        }
+
+        let mut header_len = IPV4_HEADER_SIZE;
+        if is_udp {
+            let ports = cursor.take(1)?[0];
+            let source_port = match ports >> 4 {
+                INLINE_PORT => cursor.take_u16()?,
+                index => self.port(index)?,
+            };
+            let destination_port = match ports & 0x0F {
+                INLINE_PORT => cursor.take_u16()?,
+                index => self.port(index)?,
+            };
+            header[20..22].copy_from_slice(&source_port.to_be_bytes());
+            header[22..24].copy_from_slice(&destination_port.to_be_bytes());
+            header[26..28].copy_from_slice(cursor.take(2)?);
+            header_len += UDP_HEADER_SIZE;
+        }
+
+        let rest = cursor.rest();
+        let total_len = header_len + rest.len();
+        if total_len > out.len() || total_len > u16::MAX as usize {
+            info!("decompressed packet does not fit");
+            return None;
+        }
+        header[2..4].copy_from_slice(&(total_len as u16).to_be_bytes());
+        if is_udp {
+            let udp_len = (total_len - IPV4_HEADER_SIZE) as u16;
+            header[24..26].copy_from_slice(&udp_len.to_be_bytes());
+        }
+        let checksum = header_checksum(&header[..IPV4_HEADER_SIZE]);
+        header[10..12].copy_from_slice(&checksum.to_be_bytes());
+
+        out[..header_len].copy_from_slice(&header[..header_len]);
+        out[header_len..total_len].copy_from_slice(rest);
+        Some(total_len)
+    }
+}
+
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    read: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let bytes = self.bytes.get(self.read..self.read + n)?;
+        self.read += n;
+        Some(bytes)
+    }
+
+    fn take_u16(&mut self) -> Option<u16> {
+        let bytes = self.take(2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        &self.bytes[self.read..]
+    }
+}
+
+/// internet checksum of an ipv4 header whose checksum field is zero
+fn header_checksum(header: &[u8]) -> u16 {
+    let mut sum: u32 = 0;
+    for word in header.chunks(2) {
+        sum += u16::from_be_bytes([word[0], word[1]]) as u32;
+    }
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAYLOAD: &[u8] = b"hello bus";
+
+    /// an ipv4 packet with a valid header checksum, a udp header is part of `payload`.
+    /// Tos 0, don't fragment and ttl 64, change them with [`set_header`]
+    fn ipv4(
+        out: &mut [u8; 64],
+        protocol: u8,
+        source: [u8; 4],
+        destination: [u8; 4],
+        payload: &[u8],
+    ) -> usize {
+        let len = IPV4_HEADER_SIZE + payload.len();
+        out[..len].fill(0);
+        out[0] = 0x45;
+        out[2..4].copy_from_slice(&(len as u16).to_be_bytes());
+        out[4..6].copy_from_slice(&0x1234u16.to_be_bytes());
+        out[6] = FLAG_DONT_FRAGMENT;
+        out[8] = 64;
+        out[9] = protocol;
+        out[12..16].copy_from_slice(&source);
+        out[16..20].copy_from_slice(&destination);
+        set_header(out, 10, 0);
+        out[IPV4_HEADER_SIZE..len].copy_from_slice(payload);
+        len
+    }
+
+    /// changes a byte of the ipv4 header and updates the header checksum
+    fn set_header(packet: &mut [u8], index: usize, value: u8) {
+        packet[index] = value;
+        packet[10..12].fill(0);
+        let checksum = header_checksum(&packet[..IPV4_HEADER_SIZE]);
+        packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+    }
+
+    fn udp(out: &mut [u8; 48], source_port: u16, destination_port: u16) -> usize {
+        let len = UDP_HEADER_SIZE + PAYLOAD.len();
+        out[0..2].copy_from_slice(&source_port.to_be_bytes());
+        out[2..4].copy_from_slice(&destination_port.to_be_bytes());
+        out[4..6].copy_from_slice(&(len as u16).to_be_bytes());
+        out[6..8].copy_from_slice(&0xBEEFu16.to_be_bytes());
+        out[UDP_HEADER_SIZE..len].copy_from_slice(PAYLOAD);
+        len
+    }
+
+    fn round_trip(config: &CompressionConfig, packet: &[u8], link: Option<LinkNodes>) -> usize {
+        let mut compressed = [0u8; 64];
+        let len = config.compress(packet, link, &mut compressed).unwrap();
+        assert!(len < packet.len());
+        let mut restored = [0u8; 64];
+        let restored_len = config
+            .decompress(&compressed[..len], link, &mut restored)
+            .unwrap();
+        assert_eq!(&restored[..restored_len], packet);
+        len
+    }
+
+    #[test]
+    fn udp_with_link_nodes() {
+        let config = CompressionConfig::default();
+        let mut segment = [0u8; 48];
+        let segment_len = udp(&mut segment, 9400, 9400);
+        let mut packet = [0u8; 64];
+        let len = ipv4(
+            &mut packet,
+            PROTOCOL_UDP,
+            [192, 168, 69, 3],
+            [192, 168, 69, 2],
+            &segment[..segment_len],
+        );
+        let link = LinkNodes {
+            destination: 2,
+            source: 3,
+        };
+        // flags, identification, ports and udp checksum
+        assert_eq!(
+            round_trip(&config, &packet[..len], Some(link)),
+            6 + PAYLOAD.len()
+        );
+    }
+
+    #[test]
+    fn udp_broadcast() {
+        let config = CompressionConfig::default();
+        let mut segment = [0u8; 48];
+        let segment_len = udp(&mut segment, 68, 9400);
+        let mut packet = [0u8; 64];
+        let len = ipv4(
+            &mut packet,
+            PROTOCOL_UDP,
+            [192, 168, 69, 3],
+            [255; 4],
+            &segment[..segment_len],
+        );
+        set_header(&mut packet, 6, 0);
+        let link = LinkNodes {
+            destination: BROADCAST_NODE,
+            source: 3,
+        };
+        round_trip(&config, &packet[..len], Some(link));
+    }
+
+    #[test]
+    fn udp_inline_fields_without_link_nodes() {
+        let config = CompressionConfig::default();
+        let mut segment = [0u8; 48];
+        let segment_len = udp(&mut segment, 5000, 6000);
+        let mut packet = [0u8; 64];
+        let len = ipv4(
+            &mut packet,
+            PROTOCOL_UDP,
+            [10, 0, 0, 1],
+            [10, 0, 0, 2],
+            &segment[..segment_len],
+        );
+        set_header(&mut packet, 1, 0x10);
+        set_header(&mut packet, 8, 3);
+        round_trip(&config, &packet[..len], None);
+    }
+
+    #[test]
+    fn other_protocols() {
+        let config = CompressionConfig::default();
+        let mut packet = [0u8; 64];
+        let len = ipv4(
+            &mut packet,
+            1,
+            [192, 168, 69, 3],
+            [192, 168, 69, 2],
+            PAYLOAD,
+        );
+        let link = LinkNodes {
+            destination: 2,
+            source: 3,
+        };
+        round_trip(&config, &packet[..len], Some(link));
+    }
+
+    #[test]
+    fn fragments_stay_uncompressed() {
+        let config = CompressionConfig::default();
+        let mut packet = [0u8; 64];
+        let len = ipv4(
+            &mut packet,
+            1,
+            [192, 168, 69, 3],
+            [192, 168, 69, 2],
+            PAYLOAD,
+        );
+        // more fragments follow
+        set_header(&mut packet, 6, 0x20);
+        let mut compressed = [0u8; 64];
+        assert_eq!(config.compress(&packet[..len], None, &mut compressed), None);
+    }
+
+    #[test]
+    fn udp_length_mismatch_stays_uncompressed() {
+        let config = CompressionConfig::default();
+        let mut segment = [0u8; 48];
+        let segment_len = udp(&mut segment, 9400, 9400);
+        // the udp length says one byte less than the packet carries
+        segment[4..6].copy_from_slice(&((segment_len - 1) as u16).to_be_bytes());
+        let mut packet = [0u8; 64];
+        let len = ipv4(
+            &mut packet,
+            PROTOCOL_UDP,
+            [192, 168, 69, 3],
+            [192, 168, 69, 2],
+            &segment[..segment_len],
+        );
+        let mut compressed = [0u8; 64];
+        assert_eq!(config.compress(&packet[..len], None, &mut compressed), None);
+    }
+}
@@ -0,0 +1,19 @@
+//! control frames of the scheduled modes, `[0x7E][mode][kind][destination][source]`.
+//! The mode byte keeps the frames of token passing and polling apart, the kinds are up to
+//! each mode
+pub(crate) const CONTROL_FRAME_SIZE: usize = 5;
+const CONTROL_START: u8 = 0x7E;
+pub(crate) const TOKEN_MODE: u8 = 0x70;
+pub(crate) const POLLING_MODE: u8 = 0x50;
+
+/// kind, destination and source of a control frame of `mode`, `None` for any other burst
+pub(crate) fn parse(mode: u8, burst: &[u8]) -> Option<(u8, u8, u8)> {
+    if burst.len() != CONTROL_FRAME_SIZE || burst[0] != CONTROL_START || burst[1] != mode {
+        return None;
+    }
+    Some((burst[2], burst[3], burst[4]))
+}
+
+pub(crate) fn encode(mode: u8, kind: u8, destination: u8, source: u8) -> [u8; CONTROL_FRAME_SIZE] {
+    [CONTROL_START, mode, kind, destination, source]
+}
@@ -0,0 +1,70 @@
+//! a node on a half duplex line hears its own transmissions, this keeps them from the stack
+//! no matter whether the backend already suppresses them
+use crate::fcs::crc32;
+use crate::medium::MacAddress;
+
+use core::cell::Cell;
+
+use defmt::*;
+
+/// bursts remembered by [`EchoSuppression::RecentBursts`]
+pub const ECHO_HISTORY: usize = 4;
+
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum EchoSuppression {
+    /// drop a burst read back if it is one of the last [`ECHO_HISTORY`] bursts that were
+    /// sent and was not read back before. Fragments may go out before the echo of the
+    /// previous one was read
+    RecentBursts,
+    /// drop every frame with our source address, needs the ethernet or the node medium
+    SourceAddress,
+}
+
+/// shared between the transmit and receive halves of the driver
+pub(crate) struct EchoTracker {
+    mode: Option<EchoSuppression>,
+    /// length and crc of the last bursts put on the bus whose echo was not read yet
+    sent: [Cell<Option<(usize, u32)>>; ECHO_HISTORY],
+    next: Cell<usize>,
+}
+
+impl EchoTracker {
+    pub fn new(mode: Option<EchoSuppression>) -> Self {
+        Self {
+            mode,
+            sent: Default::default(),
+            next: Cell::new(0),
+        }
+    }
+
+    pub fn on_sent(&self, burst: &[u8]) {
+        if self.mode != Some(EchoSuppression::RecentBursts) {
+            return;
+        }
+        // the oldest burst is overwritten, its echo is long gone or never came
+        let next = self.next.get();
+        self.sent[next].set(Some((burst.len(), crc32(burst))));
+        self.next.set((next + 1) % ECHO_HISTORY);
+    }
+
+    /// checks a burst as it was read from the bus, every burst that was sent is
+    /// suppressed once
+    pub fn is_echoed_burst(&self, burst: &[u8]) -> bool {
+        if self.mode != Some(EchoSuppression::RecentBursts) {
+            return false;
+        }
+        let echo = Some((burst.len(), crc32(burst)));
+        match self.sent.iter().find(|sent| sent.get() == echo) {
+            Some(sent) => {
+                sent.set(None);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// checks the source in the link header of a frame
+    pub fn is_echoed_frame(&self, source: MacAddress, address: MacAddress) -> bool {
+        self.mode == Some(EchoSuppression::SourceAddress) && source == address
+    }
+}
@@ -0,0 +1,168 @@
+//! frame check sequence: a CRC-32 (the one ethernet uses) appended to every frame, so that
+//! corrupted frames are dropped by the link instead of reaching the ip stack.
+use crate::half_duplex::BUS_FRAME_SIZE;
+use crate::{Read, ReadError, Write, WriteError};
+
+use defmt::*;
+use embassy_time::Duration;
+
+pub const FCS_LEN: usize = 4;
+
+/// buffer size that fits one full bus frame plus its fcs
+pub const FCS_BUFFER_SIZE: usize = BUS_FRAME_SIZE + FCS_LEN;
+
+const POLYNOMIAL: u32 = 0xEDB8_8320;
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static CRC_TABLE: [u32; 256] = crc_table();
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFF;
+    for &byte in data {
+        crc = (crc >> 8) ^ CRC_TABLE[((crc ^ byte as u32) & 0xFF) as usize];
+    }
+    !crc
+}
+
+/// appends the crc of every frame in little endian
+pub struct FcsWriter<W: Write, const N: usize = FCS_BUFFER_SIZE> {
+    write: W,
+    buf: [u8; N],
+}
+
+impl<W: Write, const N: usize> FcsWriter<W, N> {
+    pub fn new(write: W) -> Self {
+        Self { write, buf: [0; N] }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.write
+    }
+
+    /// copies `buf` with its crc into the buffer, returns the length
+    fn append_fcs(&mut self, buf: &[u8]) -> Result<usize, WriteError> {
+        let len = buf.len();
+        if len + FCS_LEN > N {
+            info!("frame of {} bytes does not fit the fcs buffer", len);
+            return Err(WriteError::FramingError);
+        }
+        self.buf[..len].copy_from_slice(buf);
+        self.buf[len..len + FCS_LEN].copy_from_slice(&crc32(buf).to_le_bytes());
+        Ok(len + FCS_LEN)
+    }
+}
+
+impl<W: Write, const N: usize> Write for FcsWriter<W, N> {
+    async fn write<'a>(&'a mut self, buf: &'a [u8]) -> Result<(), WriteError>
+    where
+        Self: Sized,
+    {
+        let len = self.append_fcs(buf)?;
+        self.write.write(&self.buf[..len]).await
+    }
+
+    /// the arbitration field stays at the start of the frame, the crc covers it as well
+    async fn write_arbitrated<'a>(
+        &'a mut self,
+        buf: &'a [u8],
+        arbitration: usize,
+    ) -> Result<(), WriteError>
+    where
+        Self: Sized,
+    {
+        let len = self.append_fcs(buf)?;
+        self.write
+            .write_arbitrated(&self.buf[..len], arbitration)
+            .await
+    }
+
+    async fn write_raw<'a>(&'a mut self, buf: &'a [u8]) -> Result<(), WriteError>
+    where
+        Self: Sized,
+    {
+        self.write.write_raw(buf).await
+    }
+
+    fn is_line_free(&self) -> bool {
+        self.write.is_line_free()
+    }
+
+    fn time_until_free(&self) -> Duration {
+        self.write.time_until_free()
+    }
+}
+
+/// checks and strips the crc of every frame, frames that do not match are
+/// reported as [`ReadError::ChecksumError`]
+pub struct FcsReader<R: Read, const N: usize = FCS_BUFFER_SIZE> {
+    read: R,
+    buf: [u8; N],
+}
+
+impl<R: Read, const N: usize> FcsReader<R, N> {
+    pub fn new(read: R) -> Self {
+        Self { read, buf: [0; N] }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.read
+    }
+}
+
+impl<R: Read, const N: usize> Read for FcsReader<R, N> {
+    async fn read_until_idle<'a>(&'a mut self, buf: &'a mut [u8]) -> Result<usize, ReadError>
+    where
+        Self: Sized,
+    {
+        let read = self.read.read_until_idle(&mut self.buf).await?;
+        if read < FCS_LEN {
+            return Err(ReadError::ChecksumError);
+        }
+        let len = read - FCS_LEN;
+        let mut fcs = [0; FCS_LEN];
+        fcs.copy_from_slice(&self.buf[len..read]);
+        if crc32(&self.buf[..len]) != u32::from_le_bytes(fcs) {
+            info!("dropping frame with bad fcs");
+            return Err(ReadError::ChecksumError);
+        }
+        if len > buf.len() {
+            return Err(ReadError::OverflowError);
+        }
+        buf[..len].copy_from_slice(&self.buf[..len]);
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn empty_frame() {
+        assert_eq!(crc32(&[]), 0);
+    }
+}
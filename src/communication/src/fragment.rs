@@ -0,0 +1,384 @@
+//! link layer fragmentation for uarts that cannot take a whole frame in one burst.
+//!
+//! with fragmentation enabled every burst on the bus is `[tag][tag][index][count][payload]`.
+//! The tag identifies the frame being reassembled, all fragments but the last carry the
+//! same amount of payload, so the receiver places fragment `i` at `i` times the payload
+//! of the first fragment.
+use crate::half_duplex::BUS_FRAME_SIZE;
+
+use defmt::*;
+use embassy_time::{Duration, Instant};
+
+pub const FRAGMENT_HEADER_SIZE: usize = 4;
+/// largest fragment, header included
+pub const MAX_FRAGMENT_SIZE: usize = 256;
+/// fragments are tracked in a 32 bit mask
+const MAX_FRAGMENTS: usize = 32;
+const MAX_REASSEMBLIES: usize = 2;
+
+pub struct FragmentationConfig {
+    /// size of every burst on the bus, header included, at most [`MAX_FRAGMENT_SIZE`]
+    pub fragment_size: usize,
+    /// a frame that is not complete after this long is dropped
+    pub reassembly_timeout: Duration,
+}
+
+impl Default for FragmentationConfig {
+    fn default() -> Self {
+        Self {
+            fragment_size: 128,
+            reassembly_timeout: Duration::from_millis(100),
+        }
+    }
+}
+
+pub(crate) struct Fragmenter {
+    payload_size: usize,
+    buf: [u8; MAX_FRAGMENT_SIZE],
+    next_tag: u16,
+    /// tag and next fragment of the frame at the head of the queue
+    current: Option<(u16, u8)>,
+}
+
+impl Fragmenter {
+    /// `frame_size` is the largest frame that has to be split up
+    pub fn new(config: &FragmentationConfig, tag_seed: u16, frame_size: usize) -> Self {
+        let fragment_size = config.fragment_size.min(MAX_FRAGMENT_SIZE);
+        let min_payload = (frame_size + MAX_FRAGMENTS - 1) / MAX_FRAGMENTS;
+        assert!(
+            fragment_size >= FRAGMENT_HEADER_SIZE + min_payload,
+            "fragments too small for a full frame"
+        );
+        Self {
+            payload_size: fragment_size - FRAGMENT_HEADER_SIZE,
+            buf: [0; MAX_FRAGMENT_SIZE],
+            next_tag: tag_seed,
+            current: None,
+        }
+    }
+
+    /// the next fragment of `frame` to put on the bus
+    pub fn next_fragment(&mut self, frame: &[u8]) -> &[u8] {
+        let count = ((frame.len() + self.payload_size - 1) / self.payload_size).max(1);
+        let (tag, index) = match self.current {
+            Some(current) => current,
+            None => {
+                let tag = self.next_tag;
+                self.next_tag = self.next_tag.wrapping_add(1);
+                self.current = Some((tag, 0));
+                (tag, 0)
+            }
+        };
+        let start = index as usize * self.payload_size;
+        let end = (start + self.payload_size).min(frame.len());
+        self.buf[0..2].copy_from_slice(&tag.to_be_bytes());
+        self.buf[2] = index;
+        self.buf[3] = count as u8;
+        let len = FRAGMENT_HEADER_SIZE + end - start;
+        self.buf[FRAGMENT_HEADER_SIZE..len].copy_from_slice(&frame[start..end]);
+        &self.buf[..len]
+    }
+
+    /// length of the next fragment of a frame of `len` bytes
+    pub fn next_len(&self, len: usize) -> usize {
+        let sent = match self.current {
+            Some((_, index)) => index as usize * self.payload_size,
+            None => 0,
+        };
+        FRAGMENT_HEADER_SIZE + len.saturating_sub(sent).min(self.payload_size)
+    }
+
+    /// wraps a frame that fits one burst into a fragment of its own, for link layer frames
+    /// that go out in between the fragments of the current frame
+    pub fn single<'a>(&mut self, frame: &[u8], out: &'a mut [u8]) -> &'a [u8] {
+        let tag = self.next_tag;
+        self.next_tag = self.next_tag.wrapping_add(1);
+        let len = FRAGMENT_HEADER_SIZE + frame.len();
+        out[0..2].copy_from_slice(&tag.to_be_bytes());
+        out[2] = 0;
+        out[3] = 1;
+        out[FRAGMENT_HEADER_SIZE..len].copy_from_slice(frame);
+        &out[..len]
+    }
+
+    /// the last fragment went out, returns true once the whole frame was sent
+    pub fn on_fragment_sent(&mut self) -> bool {
+        let Some((tag, index)) = self.current else {
+            return true;
+        };
+        let done = index + 1 >= self.buf[3];
+        self.current = if done { None } else { Some((tag, index + 1)) };
+        done
+    }
+
+    /// the frame is done, delivered or not
+    pub fn finish(&mut self) {
+        self.current = None;
+    }
+}
+
+struct Reassembly<const BUS: usize> {
+    tag: u16,
+    count: u8,
+    received: u32,
+    stride: usize,
+    len: usize,
+    started: Instant,
+    /// the frame was handed out, the slot is free but its buffer is still borrowed
+    complete: bool,
+    buf: [u8; BUS],
+}
+
+impl<const BUS: usize> Reassembly<BUS> {
+    fn is_free(slot: &Option<Self>) -> bool {
+        match slot {
+            Some(reassembly) => reassembly.complete,
+            None => true,
+        }
+    }
+
+    fn is_for(slot: &Option<Self>, tag: u16, count: u8) -> bool {
+        match slot {
+            Some(reassembly) => {
+                !reassembly.complete && reassembly.tag == tag && reassembly.count == count
+            }
+            None => false,
+        }
+    }
+
+    fn all_received(&self) -> bool {
+        let all = if self.count as usize == MAX_FRAGMENTS {
+            u32::MAX
+        } else {
+            (1u32 << self.count) - 1
+        };
+        self.received == all
+    }
+}
+
+pub(crate) enum Reassembled<'a> {
+    Complete(&'a [u8]),
+    Incomplete,
+    /// a reassembly was dropped, either it timed out or there was no room for it
+    Dropped,
+    Malformed,
+}
+
+/// reassembles frames of up to `BUS` bytes
+pub(crate) struct Reassembler<const BUS: usize = BUS_FRAME_SIZE> {
+    timeout: Duration,
+    slots: [Option<Reassembly<BUS>>; MAX_REASSEMBLIES],
+}
+
+impl<const BUS: usize> Reassembler<BUS> {
+    pub fn new(config: &FragmentationConfig) -> Self {
+        Self {
+            timeout: config.reassembly_timeout,
+            slots: Default::default(),
+        }
+    }
+
+    /// frees slots that waited too long, returns true if any were dropped
+    fn expire(&mut self) -> bool {
+        let now = Instant::now();
+        let mut expired = false;
+        for slot in self.slots.iter_mut() {
+            if Reassembly::is_free(slot) {
+                continue;
+            }
+            if let Some(reassembly) = slot {
+                if now >= reassembly.started + self.timeout {
+                    info!("reassembly of {} timed out", reassembly.tag);
+                    *slot = None;
+                    expired = true;
+                }
+            }
+        }
+        expired
+    }
+
+    pub fn receive<'a>(&'a mut self, fragment: &'a [u8]) -> Reassembled<'a> {
+        if fragment.len() <= FRAGMENT_HEADER_SIZE {
+            return Reassembled::Malformed;
+        }
+        let tag = u16::from_be_bytes([fragment[0], fragment[1]]);
+        let index = fragment[2];
+        let count = fragment[3];
+        let payload = &fragment[FRAGMENT_HEADER_SIZE..];
+        if count == 0 || index >= count || count as usize > MAX_FRAGMENTS {
+            return Reassembled::Malformed;
+        }
+        if count == 1 {
+            return Reassembled::Complete(payload);
+        }
+        let expired = self.expire();
+        let incomplete = if expired {
+            Reassembled::Dropped
+        } else {
+            Reassembled::Incomplete
+        };
+
+        let position = if index == 0 {
+            // a repeated first fragment restarts the reassembly
+            let position = self
+                .slots
+                .iter()
+                .position(|slot| Reassembly::is_for(slot, tag, count))
+                .or_else(|| self.slots.iter().position(Reassembly::is_free));
+            let Some(position) = position else {
+                info!("no room to reassemble {}", tag);
+                return Reassembled::Dropped;
+            };
+            self.slots[position] = Some(Reassembly {
+                tag,
+                count,
+                received: 0,
+                stride: payload.len(),
+                len: 0,
+                started: Instant::now(),
+                complete: false,
+                buf: [0; BUS],
+            });
+            position
+        } else {
+            let position = self
+                .slots
+                .iter()
+                .position(|slot| Reassembly::is_for(slot, tag, count));
+            let Some(position) = position else {
+                // the start of this frame was lost
+                return incomplete;
+            };
+            position
+        };
+
+        let Some(stride) = self.slots[position]
+            .as_ref()
+            .map(|reassembly| reassembly.stride)
+        else {
+            return Reassembled::Malformed;
+        };
+        let start = index as usize * stride;
+        let end = start + payload.len();
+        let is_last = index + 1 == count;
+        if end > BUS || (!is_last && payload.len() != stride) {
+            self.slots[position] = None;
+            return Reassembled::Malformed;
+        }
+        // the complete frame borrows the slot until the next call
+        let Some(reassembly) = self.slots[position].as_mut() else {
+            return Reassembled::Malformed;
+        };
+        reassembly.buf[start..end].copy_from_slice(payload);
+        reassembly.received |= 1 << index;
+        if is_last {
+            reassembly.len = end;
+        }
+        if !reassembly.all_received() {
+            return incomplete;
+        }
+        reassembly.complete = true;
+        Reassembled::Complete(&reassembly.buf[..reassembly.len])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: &[u8] = b"twenty bytes of data";
+
+    /// the fragments of `FRAME` with 8 bytes of payload each
+    fn split() -> [([u8; 12], usize); 3] {
+        let config = FragmentationConfig {
+            fragment_size: 12,
+            ..Default::default()
+        };
+        let mut fragmenter = Fragmenter::new(&config, 7, FRAME.len());
+        let mut fragments = [([0u8; 12], 0); 3];
+        for (i, (buf, len)) in fragments.iter_mut().enumerate() {
+            assert_eq!(fragmenter.next_len(FRAME.len()), if i < 2 { 12 } else { 8 });
+            let fragment = fragmenter.next_fragment(FRAME);
+            buf[..fragment.len()].copy_from_slice(fragment);
+            *len = fragment.len();
+            assert_eq!(fragmenter.on_fragment_sent(), i == 2);
+        }
+        fragments
+    }
+
+    fn reassemble(fragments: &[([u8; 12], usize)], order: &[usize]) -> bool {
+        let mut reassembler = Reassembler::<64>::new(&Default::default());
+        let (last, rest) = order.split_last().unwrap();
+        for &i in rest {
+            let (buf, len) = &fragments[i];
+            assert!(matches!(
+                reassembler.receive(&buf[..*len]),
+                Reassembled::Incomplete
+            ));
+        }
+        let (buf, len) = &fragments[*last];
+        match reassembler.receive(&buf[..*len]) {
+            Reassembled::Complete(frame) => {
+                assert_eq!(frame, FRAME);
+                true
+            }
+            Reassembled::Incomplete => false,
+            _ => panic!("unexpected reassembly result"),
+        }
+    }
+
+    #[test]
+    fn split_headers() {
+        let fragments = split();
+        for (i, (buf, _)) in fragments.iter().enumerate() {
+            assert_eq!(&buf[..4], &[0, 7, i as u8, 3]);
+        }
+        assert_eq!(&fragments[2].0[4..8], &FRAME[16..]);
+    }
+
+    #[test]
+    fn in_order() {
+        assert!(reassemble(&split(), &[0, 1, 2]));
+    }
+
+    #[test]
+    fn out_of_order() {
+        assert!(reassemble(&split(), &[0, 2, 1]));
+    }
+
+    #[test]
+    fn missing_fragment() {
+        assert!(!reassemble(&split(), &[0, 2]));
+    }
+
+    #[test]
+    fn missing_first_fragment() {
+        assert!(!reassemble(&split(), &[1, 2]));
+    }
+
+    #[test]
+    fn single_fragment() {
+        let mut fragmenter = Fragmenter::new(&Default::default(), 1, 64);
+        let mut out = [0u8; 16];
+        let fragment = fragmenter.single(b"ack", &mut out);
+        assert_eq!(fragment, &[0, 1, 0, 1, b'a', b'c', b'k']);
+        let mut reassembler = Reassembler::<64>::new(&Default::default());
+        assert!(matches!(
+            reassembler.receive(fragment),
+            Reassembled::Complete(b"ack")
+        ));
+    }
+
+    #[test]
+    fn malformed() {
+        let mut reassembler = Reassembler::<64>::new(&Default::default());
+        assert!(matches!(
+            reassembler.receive(&[0, 1, 0]),
+            Reassembled::Malformed
+        ));
+        assert!(matches!(
+            reassembler.receive(&[0, 1, 3, 3, 0xAA]),
+            Reassembled::Malformed
+        ));
+    }
+}
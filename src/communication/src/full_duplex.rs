@@ -0,0 +1,266 @@
+//! driver for point-to-point links with separate tx and rx lines. Nothing else shares the
+//! line, so frames go out as soon as the stack hands them over, without carrier sense,
+//! echo detection or backoff, while frames keep coming in on the other line
+use crate::backoff::BackoffPolicy;
+use crate::fragment::{Fragmenter, Reassembler};
+use crate::half_duplex::{HalfDuplexConfig, LinkShared, RxHandler, BUS_FRAME_SIZE, IP_FRAME_SIZE};
+use crate::link_state::{LinkEvent, LinkMonitor};
+use crate::medium::{MediumAdapter, Outbound, ARP_FRAME_SIZE};
+use crate::outcome::{OutcomeChannel, TransmitOutcome};
+use crate::stats::{LinkCounters, LinkStatsHandle, StatsRecorder};
+use crate::{AsyncDevice, AsyncTimer};
+use crate::{Read, Write, WriteError};
+
+use core::cell::RefCell;
+
+use defmt::*;
+use embassy_futures::select::{select3, Either3};
+use embassy_net_driver_channel::{Runner, TxRunner};
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_time::{Duration, Instant, Timer};
+use rand_core::RngCore;
+
+/// frames answered without the line, handed from the transmit to the receive loop
+pub(crate) type LocalReplies = Channel<NoopRawMutex, [u8; ARP_FRAME_SIZE], 1>;
+
+struct FullDuplexTx<W: Write, const MTU: usize, const BUS: usize> {
+    write: W,
+    tx_runner: TxRunner<'static, MTU>,
+    outcomes: Option<&'static OutcomeChannel>,
+    stats: StatsRecorder,
+    medium: MediumAdapter,
+    fragmenter: Option<Fragmenter>,
+    listen_only: bool,
+    scratch: [u8; BUS],
+}
+
+impl<W: Write, const MTU: usize, const BUS: usize> FullDuplexTx<W, MTU, BUS> {
+    /// sends the next frame of the stack. A failed write drops the frame right away,
+    /// on a line of its own there is nobody to back off for
+    async fn transmit(&mut self, replies: &LocalReplies) -> LinkEvent {
+        let buf = self.tx_runner.tx_buf().await;
+        let len = buf.len();
+        if self.listen_only {
+            self.stats.abandoned();
+            self.finish_frame(TransmitOutcome::Abandoned { collisions: 0 });
+            return LinkEvent::Nothing;
+        }
+        let end = match self.medium.stack_to_bus(buf, &mut self.scratch) {
+            Outbound::Bus(end) => end,
+            Outbound::Local(reply) => {
+                let outcome = match replies.try_send(reply) {
+                    Ok(()) => TransmitOutcome::Delivered { collisions: 0 },
+                    Err(_) => {
+                        info!("local reply pending, dropping...");
+                        TransmitOutcome::Abandoned { collisions: 0 }
+                    }
+                };
+                self.finish_frame(outcome);
+                return LinkEvent::Nothing;
+            }
+            Outbound::Drop => {
+                info!("the medium cannot carry this frame, dropping...");
+                self.stats.abandoned();
+                self.finish_frame(TransmitOutcome::Abandoned { collisions: 0 });
+                return LinkEvent::Nothing;
+            }
+        };
+        let frame = &self.scratch[..end];
+        loop {
+            let burst: &[u8] = match self.fragmenter.as_mut() {
+                Some(fragmenter) => fragmenter.next_fragment(frame),
+                None => frame,
+            };
+            if let Err(err) = self.write.write(burst).await {
+                info!("write failed: {:?}", err);
+                if matches!(err, WriteError::CollisionError) {
+                    self.stats.collision();
+                } else {
+                    self.stats.framing_error();
+                }
+                self.stats.abandoned();
+                self.finish_frame(TransmitOutcome::FramingError { collisions: 0 });
+                return LinkEvent::FramingError;
+            }
+            let frame_sent = match self.fragmenter.as_mut() {
+                Some(fragmenter) => fragmenter.on_fragment_sent(),
+                None => true,
+            };
+            if frame_sent {
+                break;
+            }
+        }
+        self.stats.frame_sent(len);
+        self.finish_frame(TransmitOutcome::Delivered { collisions: 0 });
+        LinkEvent::Traffic
+    }
+
+    async fn run(&mut self, link: &RefCell<LinkMonitor>, replies: &LocalReplies) -> ! {
+        loop {
+            let event = self.transmit(replies).await;
+            link.borrow_mut().on_transmit_event(event);
+        }
+    }
+
+    fn finish_frame(&mut self, outcome: TransmitOutcome) {
+        if let Some(fragmenter) = self.fragmenter.as_mut() {
+            fragmenter.finish();
+        }
+        self.tx_runner.tx_done();
+        if let Some(outcomes) = self.outcomes {
+            if outcomes.try_send(outcome).is_err() {
+                info!("outcome channel full, dropping {:?}", outcome);
+            }
+        }
+    }
+}
+
+/// full duplex counterpart of [`crate::half_duplex::AsyncHalfDuplexUart`] on the same
+/// [`Read`] and [`Write`] and with the same framing. Of the config the shared medium
+/// options `echo_suppression` and `jam` are ignored, `arq` and the bus access options
+/// `priority`, `tdma`, `token`, `polling` and `arbitration` panic. The other side has to
+/// run this driver as well
+pub struct AsyncFullDuplexUart<
+    R,
+    W,
+    const MTU: usize = IP_FRAME_SIZE,
+    const BUS: usize = BUS_FRAME_SIZE,
+> where
+    R: Read,
+    W: Write,
+{
+    tx_handler: FullDuplexTx<W, MTU, BUS>,
+    rx_handler: RxHandler<R, MTU, BUS>,
+    link: RefCell<LinkMonitor>,
+    stats: Option<&'static LinkCounters>,
+    shared: LinkShared,
+    replies: LocalReplies,
+}
+
+impl<R, W, const MTU: usize, const BUS: usize> AsyncFullDuplexUart<R, W, MTU, BUS>
+where
+    R: Read,
+    W: Write,
+{
+    /// takes the same arguments as [`crate::half_duplex::AsyncHalfDuplexUart::new`], so
+    /// either driver fits the same setup. Nothing backs off on a line of its own, the timer
+    /// and the policy go unused and the rng only seeds the fragment tags
+    pub fn new<T: AsyncTimer, RN: RngCore, P: BackoffPolicy>(
+        read: R,
+        write: W,
+        _timer: T,
+        runner: Runner<'static, MTU>,
+        mut rng: RN,
+        _policy: P,
+        config: HalfDuplexConfig,
+    ) -> Self {
+        assert!(config.arq.is_none(), "arq needs the half duplex driver");
+        assert!(
+            config.priority.is_none()
+                && config.tdma.is_none()
+                && config.token.is_none()
+                && config.polling.is_none()
+                && config.arbitration.is_none(),
+            "priority, tdma, token passing, polling and arbitration need the half duplex driver"
+        );
+        let (state, rx, tx) = runner.split();
+        let stats = StatsRecorder::new(config.stats);
+        let medium = MediumAdapter::new(config.medium, config.address, config.compression);
+        let fragmenter = config
+            .fragmentation
+            .as_ref()
+            .map(|fragmentation| Fragmenter::new(fragmentation, rng.next_u32() as u16, BUS));
+        let reassembler = config.fragmentation.as_ref().map(Reassembler::new);
+        Self {
+            tx_handler: FullDuplexTx {
+                write,
+                tx_runner: tx,
+                outcomes: config.outcomes,
+                stats,
+                medium,
+                fragmenter,
+                listen_only: config.listen_only,
+                scratch: [0; BUS],
+            },
+            rx_handler: RxHandler::new(read, rx)
+                .with_stats(stats)
+                .with_medium(medium)
+                .with_filter(config.address_filter, config.address)
+                .with_sniffer(config.sniffer)
+                .with_reassembler(reassembler),
+            link: RefCell::new(LinkMonitor::new(
+                state,
+                config.link_down_after_framing_errors,
+                config.silence_timeout,
+            )),
+            stats: config.stats,
+            shared: LinkShared::new(None, None, None, None, config.max_scheduled_attempts),
+            replies: Channel::new(),
+        }
+    }
+
+    /// handle to the link counters, `None` if the config did not provide any
+    pub fn stats(&self) -> Option<LinkStatsHandle> {
+        self.stats.map(LinkStatsHandle::new)
+    }
+
+    pub async fn start(&mut self) -> ! {
+        self.link.get_mut().start();
+        let Self {
+            tx_handler,
+            rx_handler,
+            link,
+            shared,
+            replies,
+            ..
+        } = self;
+        // none of the loops ever returns, so neither is dropped in the middle of a frame
+        let result = select3(
+            tx_handler.run(link, replies),
+            receive(rx_handler, shared, link, replies),
+            watch_silence(link),
+        )
+        .await;
+        match result {
+            Either3::First(never) | Either3::Second(never) | Either3::Third(never) => never,
+        }
+    }
+}
+
+async fn receive<R: Read, const MTU: usize, const BUS: usize>(
+    rx_handler: &mut RxHandler<R, MTU, BUS>,
+    shared: &LinkShared,
+    link: &RefCell<LinkMonitor>,
+    replies: &LocalReplies,
+) -> ! {
+    loop {
+        let event = rx_handler.read_with_replies(shared, replies).await;
+        link.borrow_mut().on_event(event);
+    }
+}
+
+/// takes the link down once it was silent for the silence timeout
+async fn watch_silence(link: &RefCell<LinkMonitor>) -> ! {
+    loop {
+        let deadline = link.borrow().silence_deadline();
+        match deadline {
+            Some(deadline) if Instant::now() >= deadline => link.borrow_mut().on_silence(),
+            Some(deadline) => Timer::at(deadline).await,
+            None => Timer::after(SILENCE_POLL).await,
+        }
+    }
+}
+
+impl<R, W, const MTU: usize, const BUS: usize> AsyncDevice for AsyncFullDuplexUart<R, W, MTU, BUS>
+where
+    R: Read,
+    W: Write,
+{
+    async fn start(&mut self) -> ! {
+        AsyncFullDuplexUart::start(self).await
+    }
+}
+
+/// how often the silence watch looks again while the link is down
+const SILENCE_POLL: Duration = Duration::from_millis(100);
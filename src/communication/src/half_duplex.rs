@@ -1,54 +1,275 @@
 
+use crate::address_filter::AddressFilter;
+use crate::arbitration::{Arbiter, ArbitrationConfig, ARBITRATION_FIELD_SIZE};
+use crate::arq::{
+    ArqConfig, ArqReceiver, ArqSender, ArqShared, Received, ACK_LEN, ARQ_HEADER_SIZE,
+};
+use crate::backoff::{BackoffPolicy, BinaryExponential};
+use crate::compression::CompressionConfig;
+use crate::echo::{EchoSuppression, EchoTracker};
+use crate::fragment::{
+    FragmentationConfig, Fragmenter, Reassembled, Reassembler, FRAGMENT_HEADER_SIZE,
+    MAX_FRAGMENT_SIZE,
+};
+use crate::full_duplex::LocalReplies;
+use crate::jam::JamConfig;
+use crate::link_state::{LinkEvent, LinkMonitor};
+use crate::medium::{
+    ethernet_addresses, node_id, LinkMedium, MacAddress, MediumAdapter, Outbound, ARP_FRAME_SIZE,
+    BROADCAST_NODE,
+};
+use crate::outcome::{OutcomeChannel, TransmitOutcome};
+use crate::polling::{PollFrame, PollState, PollingConfig};
+use crate::priority::{PriorityConfig, PriorityQueues};
+use crate::sniffer::{SniffedFrame, SnifferChannel};
+use crate::stats::{LinkCounters, LinkStatsHandle, StatsRecorder};
+use crate::tdma::{is_beacon, TdmaConfig, TdmaSchedule, BEACON};
+use crate::token::{ControlFrame, TokenConfig, TokenState};
 use crate::BackoffHandler;
 use crate::{AsyncDevice, AsyncTimer};
-use crate::{Read, Write, WriteError};
+use crate::{Read, ReadError, Write, WriteError};
 
+use core::cell::Cell;
+use core::cmp::max;
 use core::future;
-
 use core::sync::atomic::{AtomicBool, Ordering};
 
 use defmt::*;
-use embassy_futures::select::select;
-use embassy_net_driver_channel::{Runner, RxRunner, State, StateRunner, TxRunner};
+use embassy_futures::select::{select, select3, Either, Either3};
+use embassy_net_driver_channel::{Runner, RxRunner, State, TxRunner};
+use embassy_time::{Duration, Instant, Timer};
 
 use rand_core::RngCore;
 
-pub type CommunicationState = State<IP_FRAME_SIZE, RECEIVE_SENDER_SIZE, TRANSMIT_CHANNEL_SIZE>;
-struct TxHandler<T, W, R>
+/// channel state shared with the stack, the sizes default to a full ethernet frame and ten slots
+/// per direction. Use `<CommunicationState>::new()` to get the defaults in an expression
+pub type CommunicationState<
+    const MTU: usize = IP_FRAME_SIZE,
+    const RX: usize = RECEIVE_SENDER_SIZE,
+    const TX: usize = TRANSMIT_CHANNEL_SIZE,
+> = State<MTU, RX, TX>;
+
+/// optional behaviour of [`AsyncHalfDuplexUart`], start from `Default::default()`
+/// and change the fields you need. [`crate::full_duplex::AsyncFullDuplexUart`] takes the same config
+pub struct HalfDuplexConfig {
+    /// mac address of this node, the same one the stack was given
+    pub address: MacAddress,
+    /// what goes on the bus, every node on the bus must agree on this
+    pub medium: LinkMedium,
+    /// compress ipv4 and udp headers, needs the ip or the node medium. Every node on the
+    /// bus must agree on this
+    pub compression: Option<CompressionConfig>,
+    /// only hand frames for this node, broadcast and the configured groups to the stack
+    pub address_filter: Option<AddressFilter>,
+    /// receives the final status of every frame handed to the driver
+    pub outcomes: Option<&'static OutcomeChannel>,
+    /// counters kept up to date by the driver, see [`AsyncHalfDuplexUart::stats`]
+    pub stats: Option<&'static LinkCounters>,
+    /// report the link down to the stack after this many framing errors in a row
+    pub link_down_after_framing_errors: Option<usize>,
+    /// report the link down to the stack when no frame was read for this long
+    pub silence_timeout: Option<Duration>,
+    /// acknowledge and repeat unicast frames, every node on the bus must agree on this.
+    /// Needs the ethernet or the node medium, the ip medium carries no addresses
+    pub arq: Option<ArqConfig>,
+    /// split frames into short bursts, every node on the bus must agree on this
+    pub fragmentation: Option<FragmentationConfig>,
+    /// keeps our own transmissions from being handed back to the stack
+    pub echo_suppression: Option<EchoSuppression>,
+    /// jam the bus after a collision and drop jams of other nodes
+    pub jam: Option<JamConfig>,
+    /// receives a copy of everything read from the bus, see [`crate::sniffer`]
+    pub sniffer: Option<&'static SnifferChannel>,
+    /// never transmit, frames of the stack are dropped and reported as abandoned
+    pub listen_only: bool,
+    /// transmit only in the slots of this node instead of contending for the bus, cannot
+    /// be combined with arq
+    pub tdma: Option<TdmaConfig>,
+    /// transmit only while holding the token, needs a mac address from
+    /// [`crate::medium::node_mac`] and cannot be combined with arq or tdma
+    pub token: Option<TokenConfig>,
+    /// master/slave polling, needs a mac address from [`crate::medium::node_mac`] and
+    /// cannot be combined with arq, tdma or token passing
+    pub polling: Option<PollingConfig>,
+    /// with tdma, token passing or polling nothing backs off, a frame whose write failed
+    /// this often is dropped
+    pub max_scheduled_attempts: usize,
+    /// start every burst with a priority field and let the more urgent frame win the bus,
+    /// see [`crate::arbitration`]. Needs a mac address from [`crate::medium::node_mac`] and
+    /// cannot be combined with arq, fragmentation or the scheduled modes
+    pub arbitration: Option<ArbitrationConfig>,
+    /// queue frames of the stack by class and send the most urgent ones first, see
+    /// [`crate::priority`]
+    pub priority: Option<PriorityConfig>,
+}
+
+impl Default for HalfDuplexConfig {
+    fn default() -> Self {
+        Self {
+            address: [0; 6],
+            medium: LinkMedium::Ethernet,
+            compression: None,
+            address_filter: None,
+            outcomes: None,
+            stats: None,
+            link_down_after_framing_errors: Some(8),
+            silence_timeout: None,
+            arq: None,
+            fragmentation: None,
+            echo_suppression: Some(EchoSuppression::RecentBursts),
+            jam: None,
+            sniffer: None,
+            listen_only: false,
+            tdma: None,
+            token: None,
+            polling: None,
+            max_scheduled_attempts: 3,
+            arbitration: None,
+            priority: None,
+        }
+    }
+}
+
+/// handed between the receive and transmit halves of the driver
+pub(crate) struct LinkShared {
+    arq: ArqShared,
+    /// a frame answered without the bus, to be handed back to the stack
+    local_reply: Cell<Option<[u8; ARP_FRAME_SIZE]>>,
+    echo: EchoTracker,
+    tdma: Option<TdmaSchedule>,
+    token: Option<TokenState>,
+    polling: Option<PollState>,
+    max_scheduled_attempts: usize,
+    /// until when framing errors are blamed on a jam
+    jam_until: Cell<Option<Instant>>,
+}
+
+impl LinkShared {
+    pub(crate) fn new(
+        echo: Option<EchoSuppression>,
+        tdma: Option<TdmaSchedule>,
+        token: Option<TokenState>,
+        polling: Option<PollState>,
+        max_scheduled_attempts: usize,
+    ) -> Self {
+        Self {
+            arq: Default::default(),
+            local_reply: Cell::new(None),
+            echo: EchoTracker::new(echo),
+            tdma,
+            token,
+            polling,
+            max_scheduled_attempts,
+            jam_until: Cell::new(None),
+        }
+    }
+
+    /// a jam was sent or read just now
+    fn on_jam(&self, jam: &JamConfig) {
+        self.jam_until.set(Some(Instant::now() + jam.window));
+    }
+
+    fn in_jam_window(&self) -> bool {
+        match self.jam_until.get() {
+            Some(until) => Instant::now() <= until,
+            None => false,
+        }
+    }
+
+    /// attempts per frame when the bus is scheduled and nothing backs off
+    fn scheduled_attempts(&self) -> Option<usize> {
+        let scheduled = self.tdma.is_some() || self.token.is_some() || self.polling.is_some();
+        scheduled.then_some(self.max_scheduled_attempts)
+    }
+}
+
+/// what happened to the frame currently being transmitted
+#[derive(Default)]
+struct FrameAttempts {
+    collisions: usize,
+    last_was_framing_error: bool,
+    /// failed writes on a scheduled bus, where nothing backs off
+    failed_writes: usize,
+}
+
+/// what the transmit half takes from the config, built once in the driver constructor
+struct TxOptions {
+    outcomes: Option<&'static OutcomeChannel>,
+    stats: StatsRecorder,
+    medium: MediumAdapter,
+    arq: Option<ArqSender>,
+    fragmenter: Option<Fragmenter>,
+    jam: Option<JamConfig>,
+    arbiter: Option<Arbiter>,
+    queues: Option<PriorityQueues>,
+}
+
+struct TxHandler<T, W, R, P, const MTU: usize = IP_FRAME_SIZE, const BUS: usize = BUS_FRAME_SIZE>
 where
     T: AsyncTimer,
     W: Write,
     R: RngCore,
+    P: BackoffPolicy,
 {
     write: W,
-    tx_runner: TxRunner<'static, IP_FRAME_SIZE>,
-    backoff_handler: BackoffHandler<T, R>,
+    tx_runner: TxRunner<'static, MTU>,
+    backoff_handler: BackoffHandler<T, R, P>,
     in_backoff: AtomicBool,
+    attempts: FrameAttempts,
+    outcomes: Option<&'static OutcomeChannel>,
+    stats: StatsRecorder,
+    medium: MediumAdapter,
+    arq: Option<ArqSender>,
+    fragmenter: Option<Fragmenter>,
+    jam: Option<JamConfig>,
+    arbiter: Option<Arbiter>,
+    queues: Option<PriorityQueues>,
+    scratch: [u8; BUS],
 }
 
-impl<T, W, R> TxHandler<T, W, R>
+impl<T, W, R, P, const MTU: usize, const BUS: usize> TxHandler<T, W, R, P, MTU, BUS>
 where
     T: AsyncTimer,
     W: Write,
     R: RngCore,
+    P: BackoffPolicy,
 {
-    pub fn new(timer: T, write: W, tx_runner: TxRunner<'static, IP_FRAME_SIZE>, rng: R) -> Self {
+    pub fn new(
+        write: W,
+        tx_runner: TxRunner<'static, MTU>,
+        backoff_handler: BackoffHandler<T, R, P>,
+        options: TxOptions,
+    ) -> Self {
         Self {
             write,
             tx_runner,
-            backoff_handler: BackoffHandler::new(timer, rng),
+            backoff_handler,
             in_backoff: AtomicBool::new(false),
+            attempts: Default::default(),
+            outcomes: options.outcomes,
+            stats: options.stats,
+            medium: options.medium,
+            arq: options.arq,
+            fragmenter: options.fragmenter,
+            jam: options.jam,
+            arbiter: options.arbiter,
+            queues: options.queues,
+            scratch: [0; BUS],
         }
     }
     /*  CORRECTNESS:
     this function runs in 'parallel' with a receive function using a select! loop. Select works by dropping
     the future that does not complete. If this future is dropped: the following happens ->
     if we are in backoff mode and awiting backoff, this is not problematic b/c resume_backoff is stateful
-    if we are in await_idle, the future will always be dropped when rx receives something
+    if we are in await_idle, it only polls the line, so it can be dropped at any point
     if we try to increment backoff this is done synchronously so it cannot be dropped. i.e, if internal transmit is
     run after the await, then we are guaranteed to increment the backoff
+    if we are waiting for an ack, the deadline is stored in the arq state, so the wait is resumed on the next call
      */
-    pub async fn transmit(&mut self) {
+    pub(crate) async fn transmit(&mut self, shared: &LinkShared) -> LinkEvent {
+        if let Some(event) = self.send_pending_ack(shared).await {
+            return event;
+        }
         if self.in_backoff.load(Ordering::Relaxed) {
             self.backoff_handler
                 .resume_backoff()
@@ -56,125 +277,994 @@ where
                 .expect("timer should never be uninitialized!");
             self.in_backoff.store(false, Ordering::Relaxed);
         }
-        if !self.write.is_line_free() {
-            self.increment_backoff();
+        if let Some(event) = self.await_ack(shared).await {
+            return event;
+        }
+        if let Some(event) = self.await_token(shared).await {
+            return event;
+        }
+        if let Some(event) = self.await_turn(shared).await {
+            return event;
+        }
+        // only sense the line once there is a frame, deferrals count against that frame
+        match shared.tdma.as_ref() {
+            Some(tdma) if tdma.sends_beacon() => {
+                match select(Timer::at(tdma.next_beacon()), self.wait_frame()).await {
+                    Either::First(()) => return self.send_beacon(shared).await,
+                    Either::Second(()) => {}
+                }
+            }
+            _ => self.wait_frame().await,
+        }
+        let Some(buf) = Self::current_frame(&mut self.queues, &mut self.tx_runner) else {
+            return LinkEvent::Nothing;
+        };
+        let len = buf.len();
+        let destination = self.medium.unicast_destination(buf);
+        let arbitration = self.arbiter.map(|arbiter| arbiter.field(buf));
+        // the link header goes in front of the translated frame
+        let outbound = self
+            .medium
+            .stack_to_bus(buf, &mut self.scratch[LINK_HEADER_SIZE..]);
+        let end = match outbound {
+            Outbound::Bus(translated) => LINK_HEADER_SIZE + translated,
+            Outbound::Local(reply) => {
+                shared.local_reply.set(Some(reply));
+                // answered without the bus, as good as delivered
+                self.finish_frame(TransmitOutcome::Delivered { collisions: 0 });
+                return LinkEvent::Nothing;
+            }
+            Outbound::Drop => {
+                info!("the medium cannot carry this frame, dropping...");
+                self.stats.abandoned();
+                self.finish_frame(TransmitOutcome::Abandoned { collisions: 0 });
+                return LinkEvent::Nothing;
+            }
+        };
+        if shared.tdma.is_some() {
+            // neither arq nor arbitration go with tdma, the frame is the whole burst
+            let frame_len = end - LINK_HEADER_SIZE;
+            let burst_len = match self.fragmenter.as_ref() {
+                Some(fragmenter) => fragmenter.next_len(frame_len),
+                None => frame_len,
+            };
+            if let Some(event) = self.await_slot(shared, burst_len).await {
+                return event;
+            }
+        } else if shared.scheduled_attempts().is_none() {
+            // a busy line is no collision, the frame goes out once it is idle. Only real
+            // collisions back off
             self.await_idle().await;
         }
-        let buf = self.tx_runner.tx_buf().await;
-        let transmit_result = self.write.write(buf).await;
+        let mut start = match self.arq.as_mut() {
+            Some(arq) => arq.prepend_header(&mut self.scratch, LINK_HEADER_SIZE, destination),
+            None => LINK_HEADER_SIZE,
+        };
+        if let Some(field) = arbitration {
+            start -= ARBITRATION_FIELD_SIZE;
+            self.scratch[start..start + ARBITRATION_FIELD_SIZE].copy_from_slice(&field);
+        }
+        let frame = &self.scratch[start..end];
+        let burst: &[u8] = match self.fragmenter.as_mut() {
+            Some(fragmenter) => fragmenter.next_fragment(frame),
+            None => frame,
+        };
+        let transmit_result = match arbitration {
+            Some(_) => {
+                self.write
+                    .write_arbitrated(burst, ARBITRATION_FIELD_SIZE)
+                    .await
+            }
+            None => self.write.write(burst).await,
+        };
+        if transmit_result.is_ok() {
+            shared.echo.on_sent(burst);
+        }
         // if an error happened: try again / cancel if too many errors
         match transmit_result {
-            Ok(_) => self.on_transmit_complete(),
-            Err(err) => match err {
-                WriteError::FramingError => self.increment_backoff(),
-                WriteError::CollisionError => self.increment_backoff(),
-                _ => self.increment_backoff(),
-            },
+            Ok(_) => {
+                let frame_sent = match self.fragmenter.as_mut() {
+                    Some(fragmenter) => fragmenter.on_fragment_sent(),
+                    None => true,
+                };
+                if !frame_sent {
+                    // the backoff attempts count per fragment, the next one starts fresh
+                    self.backoff_handler.clear();
+                    return LinkEvent::Traffic;
+                }
+                self.stats.frame_sent(len);
+                if let Some(token) = shared.token.as_ref() {
+                    token.on_frame_sent();
+                }
+                if let Some(polling) = shared.polling.as_ref() {
+                    polling.on_frame_sent();
+                }
+                let awaiting_ack = match self.arq.as_mut() {
+                    Some(arq) => arq.on_sent(),
+                    None => false,
+                };
+                if !awaiting_ack {
+                    self.on_transmit_complete();
+                }
+                LinkEvent::Traffic
+            }
+            Err(WriteError::ArbitrationLost) => {
+                // not a collision, the winner is still sending its frame. Try again as soon
+                // as it is done, the backoff stays for real collisions
+                self.stats.arbitration_lost();
+                self.await_idle().await;
+                LinkEvent::Traffic
+            }
+            Err(err) => {
+                let collision = matches!(err, WriteError::CollisionError);
+                let event = match err {
+                    WriteError::CollisionError => {
+                        self.stats.collision();
+                        self.attempts.collisions += 1;
+                        self.attempts.last_was_framing_error = false;
+                        LinkEvent::Traffic
+                    }
+                    _ => {
+                        self.stats.framing_error();
+                        self.attempts.last_was_framing_error = true;
+                        LinkEvent::FramingError
+                    }
+                };
+                if let Some(max_attempts) = shared.scheduled_attempts() {
+                    self.on_failed_write(max_attempts);
+                    return event;
+                }
+                self.increment_backoff();
+                if collision {
+                    self.send_jam(shared).await;
+                }
+                event
+            }
+        }
+    }
+
+    /// corrupts whatever else is on the bus, dropping this halfway is harmless as the
+    /// backoff was already recorded
+    async fn send_jam(&mut self, shared: &LinkShared) {
+        let Some(jam) = self.jam else {
+            return;
         };
+        shared.on_jam(&jam);
+        if let Err(err) = self.write.write_raw(jam.pattern).await {
+            info!("could not send jam: {:?}", err);
+            return;
+        }
+        self.stats.jam_sent();
+    }
+
+    /// waits until a burst of `len` bytes fits a slot of this node, `None` once it may go
+    /// out. The beacon node sends its beacon instead if that is due first. Only timers are
+    /// awaited, so dropping this is harmless
+    async fn await_slot(&mut self, shared: &LinkShared, len: usize) -> Option<LinkEvent> {
+        let tdma = shared.tdma.as_ref()?;
+        loop {
+            let now = Instant::now();
+            let next_slot = tdma.next_slot(now, len);
+            if tdma.sends_beacon() && next_slot.map_or(true, |slot| tdma.next_beacon() <= slot) {
+                Timer::at(tdma.next_beacon()).await;
+                return Some(self.send_beacon(shared).await);
+            }
+            let Some(slot) = next_slot else {
+                // no beacon yet
+                Timer::after(tdma.slot()).await;
+                continue;
+            };
+            if slot <= now {
+                return None;
+            }
+            Timer::at(slot).await;
+        }
+    }
+
+    async fn send_beacon(&mut self, shared: &LinkShared) -> LinkEvent {
+        let Some(tdma) = shared.tdma.as_ref() else {
+            return LinkEvent::Nothing;
+        };
+        let result = self.write.write(&BEACON).await;
+        // the superframe moves on even if the beacon was lost
+        tdma.on_beacon_sent();
+        match result {
+            Ok(_) => {
+                shared.echo.on_sent(&BEACON);
+                self.stats.beacon_sent();
+                LinkEvent::Traffic
+            }
+            Err(err) => {
+                info!("could not send beacon: {:?}", err);
+                LinkEvent::FramingError
+            }
+        }
+    }
+
+    /// a write failed on a scheduled bus, the frame is repeated the next time this node
+    /// may transmit
+    fn on_failed_write(&mut self, max_attempts: usize) {
+        self.attempts.failed_writes += 1;
+        if self.attempts.failed_writes >= max_attempts {
+            info!("too many failed writes...");
+            self.abandon_frame();
+        }
+    }
+
+    /// token mode: waits until this node holds the token and passes it on once the hold
+    /// budget is used or nothing is queued, `None` once a frame may go out. All state is
+    /// kept in the token state, so dropping this is harmless
+    async fn await_token(&mut self, shared: &LinkShared) -> Option<LinkEvent> {
+        let token = shared.token.as_ref()?;
+        loop {
+            let now = Instant::now();
+            if !token.is_holding() {
+                if token.needs_join(now) {
+                    let join = token.join_frame();
+                    // the only frame that goes out without the token
+                    self.await_idle().await;
+                    if self.send_control(shared, &join.encode()).await {
+                        token.on_join_sent();
+                    }
+                    return Some(LinkEvent::Traffic);
+                }
+                if let Some((successor, deadline)) = token.pass_deadline() {
+                    if now < deadline {
+                        Timer::at(deadline).await;
+                    } else {
+                        token.on_pass_timeout(successor);
+                    }
+                    continue;
+                }
+                match token.regeneration_deadline() {
+                    Some(deadline) if now >= deadline => {
+                        token.regenerate();
+                        self.stats.token_regenerated();
+                    }
+                    Some(deadline) => Timer::at(deadline).await,
+                    None => future::pending().await,
+                }
+                continue;
+            }
+            if token.needs_leave() {
+                let leave = token.leave_frame();
+                // the others drop this node once it does not take the token, even if this is lost
+                self.send_control(shared, &leave.encode()).await;
+                token.on_leave_sent();
+                continue;
+            }
+            let queued = self.has_frame();
+            if queued && token.is_member() && token.has_budget() {
+                return None;
+            }
+            match token.successor() {
+                Some(successor) => {
+                    let pass = token.token_frame(successor);
+                    if self.send_control(shared, &pass.encode()).await {
+                        token.on_passed(successor);
+                    }
+                    return Some(LinkEvent::Traffic);
+                }
+                None if token.is_member() => {
+                    // alone in the ring, the token stays here
+                    self.wait_frame().await;
+                    token.restart_hold();
+                }
+                None => {
+                    token.drop_token();
+                    return Some(LinkEvent::Nothing);
+                }
+            }
+        }
+    }
+
+    /// polling mode: the master sends its own frames and then polls the next slave once
+    /// the last one answered or timed out, a slave sends its frames and the end of its
+    /// response once it was polled. `None` once a frame may go out
+    async fn await_turn(&mut self, shared: &LinkShared) -> Option<LinkEvent> {
+        let polling = shared.polling.as_ref()?;
+        if !polling.is_master() {
+            let Some(master) = polling.polled_by() else {
+                // the receiver completes with the poll, which drops this future
+                return Some(future::pending().await);
+            };
+            if polling.has_budget() && self.has_frame() {
+                return None;
+            }
+            let end = polling.end_frame(master);
+            self.send_control(shared, &end.encode()).await;
+            polling.on_response_sent();
+            return Some(LinkEvent::Traffic);
+        }
+        if let Some((_, deadline)) = polling.awaiting() {
+            // the end frame of the slave completes the receiver first, which drops this future
+            Timer::at(deadline).await;
+            self.stats.missed_poll();
+            polling.on_response_timeout();
+        }
+        if polling.has_budget() && self.has_frame() {
+            return None;
+        }
+        let poll = polling.next_poll();
+        if self.send_control(shared, &poll.encode()).await {
+            polling.on_polled();
+        }
+        Some(LinkEvent::Traffic)
+    }
+
+    /// puts a control frame on the bus, true if it went out
+    async fn send_control(&mut self, shared: &LinkShared, frame: &[u8]) -> bool {
+        match self.write.write(frame).await {
+            Ok(_) => {
+                shared.echo.on_sent(frame);
+                true
+            }
+            Err(err) => {
+                info!("could not send control frame: {:?}", err);
+                false
+            }
+        }
+    }
+
+    /// listen only: takes frames from the stack without touching the bus
+    pub(crate) async fn discard(&mut self) -> LinkEvent {
+        self.wait_frame().await;
+        self.stats.abandoned();
+        self.finish_frame(TransmitOutcome::Abandoned { collisions: 0 });
+        LinkEvent::Nothing
     }
 
     fn on_transmit_complete(&mut self) {
-        self.tx_runner.tx_done();
+        let collisions = self.attempts.collisions;
+        self.finish_frame(TransmitOutcome::Delivered { collisions });
+    }
+
+    /// sends the acknowledgement the receiver asked for, ahead of any queued frame and
+    /// without backoff. A lost acknowledgement only costs the sender a repetition
+    async fn send_pending_ack(&mut self, shared: &LinkShared) -> Option<LinkEvent> {
+        let ack = self.arq.as_ref()?.pending_ack(&shared.arq)?;
+        self.await_idle().await;
+        if let Some(arq) = self.arq.as_ref() {
+            arq.ack_sent(&shared.arq);
+        }
+        // the receiver reassembles every burst before it looks at the arq header
+        let mut wrapped = [0; FRAGMENT_HEADER_SIZE + ACK_LEN];
+        let burst: &[u8] = match self.fragmenter.as_mut() {
+            Some(fragmenter) => fragmenter.single(&ack, &mut wrapped),
+            None => &ack,
+        };
+        match self.write.write(burst).await {
+            Ok(_) => shared.echo.on_sent(burst),
+            Err(err) => info!("could not send ack: {:?}", err),
+        }
+        Some(LinkEvent::Traffic)
+    }
+
+    /// waits for the acknowledgement of the last unicast frame, a timeout is handled
+    /// like a collision and repeats the frame through the backoff path
+    async fn await_ack(&mut self, shared: &LinkShared) -> Option<LinkEvent> {
+        let arq = self.arq.as_ref()?;
+        let awaiting = arq.awaiting?;
+        if arq.is_acked(&awaiting, &shared.arq) {
+            self.on_transmit_complete();
+            return Some(LinkEvent::Traffic);
+        }
+        // the receiver completes when the ack arrives, which drops this future
+        Timer::at(awaiting.deadline).await;
+        info!("no ack for frame {}", awaiting.seq);
+        self.stats.ack_timeout();
+        if let Some(arq) = self.arq.as_mut() {
+            arq.awaiting = None;
+        }
+        self.attempts.last_was_framing_error = false;
+        self.increment_backoff();
+        Some(LinkEvent::Nothing)
+    }
+
+    fn finish_frame(&mut self, outcome: TransmitOutcome) {
+        if let Some(arq) = self.arq.as_mut() {
+            arq.finish();
+        }
+        if let Some(fragmenter) = self.fragmenter.as_mut() {
+            fragmenter.finish();
+        }
+        self.frame_done();
         self.backoff_handler.clear();
         self.in_backoff.store(false, Ordering::Relaxed);
+        self.attempts = Default::default();
+        self.report(outcome);
+    }
+
+    fn report(&self, outcome: TransmitOutcome) {
+        if let Some(outcomes) = self.outcomes {
+            if outcomes.try_send(outcome).is_err() {
+                info!("outcome channel full, dropping {:?}", outcome);
+            }
+        }
+    }
+
+    /// moves the frames of the stack into their class queues, up to the first frame whose
+    /// queue is full. That one stays in the channel, so the stack sees the backpressure
+    fn intake(&mut self) {
+        let Some(queues) = self.queues.as_mut() else {
+            return;
+        };
+        while let Some(buf) = self.tx_runner.try_tx_buf() {
+            if queues.push(buf).is_err() {
+                return;
+            }
+            self.tx_runner.tx_done();
+        }
+    }
+
+    /// true if a frame is waiting to be sent
+    fn has_frame(&mut self) -> bool {
+        self.intake();
+        match self.queues.as_ref() {
+            Some(queues) => !queues.is_empty(),
+            None => self.tx_runner.try_tx_buf().is_some(),
+        }
+    }
+
+    /// resolves once a frame is waiting to be sent
+    async fn wait_frame(&mut self) {
+        if self.has_frame() {
+            return;
+        }
+        self.tx_runner.tx_buf().await;
+        self.intake();
+    }
+
+    /// the frame being sent, the same one until [`Self::frame_done`]
+    fn current_frame<'a>(
+        queues: &'a mut Option<PriorityQueues>,
+        tx_runner: &'a mut TxRunner<'static, MTU>,
+    ) -> Option<&'a mut [u8]> {
+        match queues.as_mut() {
+            Some(queues) => queues.current(),
+            None => tx_runner.try_tx_buf(),
+        }
+    }
+
+    fn frame_done(&mut self) {
+        match self.queues.as_mut() {
+            Some(queues) => queues.pop_current(),
+            None => self.tx_runner.tx_done(),
+        }
     }
+
     /**
+     * resolves once the writer reports the line as free, i.e. it has been quiet for the
+     * inter frame gap.
      * correctness: Since this is used in a select with the rx component in a loop,
-     * having the rx future complete will drop this future, and the next future constructed
-     * will have "waited for idle"
+     * having the rx future complete will drop this future, which is fine as it holds no state
      */
     async fn await_idle(&mut self) {
-        let () = future::pending().await;
+        while !self.write.is_line_free() {
+            Timer::after(max(self.write.time_until_free(), MIN_IDLE_POLL)).await;
+        }
     }
 
     /**
      * note: The smolltcp stack does not have any way for a device to report errors to the stack.
-     * If you are really worried about this, use a protocol like tcp or introduce an on_error function
+     * Abandoned frames are reported on the outcome channel of the config instead
      */
     fn increment_backoff(&mut self) {
         self.in_backoff.store(true, Ordering::Relaxed);
 
         if let Err(_) = self.backoff_handler.increment_backoff() {
             info!("too many backoffs attempted...");
-            // TODO: add a user defined error handler for this ? ?
-            self.on_transmit_complete();
+            self.abandon_frame();
+        } else {
+            self.stats.backoff();
         }
     }
+
+    fn abandon_frame(&mut self) {
+        self.stats.abandoned();
+        let collisions = self.attempts.collisions;
+        let outcome = if self.attempts.last_was_framing_error {
+            TransmitOutcome::FramingError { collisions }
+        } else {
+            TransmitOutcome::Abandoned { collisions }
+        };
+        self.finish_frame(outcome);
+    }
 }
 
-pub struct RxHandler<R: Read> {
-    rx_runner: RxRunner<'static, IP_FRAME_SIZE>,
+pub struct RxHandler<R: Read, const MTU: usize = IP_FRAME_SIZE, const BUS: usize = BUS_FRAME_SIZE> {
+    rx_runner: RxRunner<'static, MTU>,
     read: R,
+    stats: StatsRecorder,
+    medium: MediumAdapter,
+    address: MacAddress,
+    filter: Option<AddressFilter>,
+    sniffer: Option<&'static SnifferChannel>,
+    jam: Option<JamConfig>,
+    arq: Option<ArqReceiver>,
+    reassembler: Option<Reassembler<BUS>>,
+    /// every burst starts with an arbitration field
+    arbitration: bool,
+    scratch: [u8; BUS],
 }
-impl<R: Read> RxHandler<R> {
-    pub fn new(read: R, rx_runner: RxRunner<'static, IP_FRAME_SIZE>) -> Self {
-        Self { read, rx_runner }
+impl<R: Read, const MTU: usize, const BUS: usize> RxHandler<R, MTU, BUS> {
+    pub fn new(read: R, rx_runner: RxRunner<'static, MTU>) -> Self {
+        assert!(
+            BUS >= bus_frame_size(MTU),
+            "bus frames must fit the mtu plus the link header"
+        );
+        Self {
+            read,
+            rx_runner,
+            stats: Default::default(),
+            medium: MediumAdapter::new(LinkMedium::Ethernet, [0; 6], None),
+            address: [0; 6],
+            filter: None,
+            sniffer: None,
+            jam: None,
+            arq: None,
+            reassembler: None,
+            arbitration: false,
+            scratch: [0; BUS],
+        }
+    }
+    pub(crate) fn with_stats(mut self, stats: StatsRecorder) -> Self {
+        self.stats = stats;
+        self
+    }
+    pub(crate) fn with_medium(mut self, medium: MediumAdapter) -> Self {
+        self.medium = medium;
+        self
+    }
+    pub(crate) fn with_filter(
+        mut self,
+        filter: Option<AddressFilter>,
+        address: MacAddress,
+    ) -> Self {
+        self.filter = filter;
+        self.address = address;
+        self
+    }
+    pub(crate) fn with_sniffer(mut self, sniffer: Option<&'static SnifferChannel>) -> Self {
+        self.sniffer = sniffer;
+        self
+    }
+    pub(crate) fn with_jam(mut self, jam: Option<JamConfig>) -> Self {
+        self.jam = jam;
+        self
+    }
+    pub(crate) fn with_arq(mut self, arq: Option<ArqReceiver>) -> Self {
+        self.arq = arq;
+        self
+    }
+    pub(crate) fn with_arbitration(mut self, arbitration: bool) -> Self {
+        self.arbitration = arbitration;
+        self
+    }
+    pub(crate) fn with_reassembler(mut self, reassembler: Option<Reassembler<BUS>>) -> Self {
+        self.reassembler = reassembler;
+        self
     }
-    pub async fn read(&mut self) {
+    /// hands a frame that never went over the bus to the stack
+    pub(crate) async fn deliver_local(&mut self, frame: &[u8]) {
         let buf = self.rx_runner.rx_buf().await;
-        let r = self.read.read_until_idle(buf).await;
-        if let Ok(s) = r {
-            self.rx_runner.rx_done(s);
-        } else {
-            info!("read lost...");
+        if frame.len() > buf.len() {
+            info!("local reply does not fit the mtu");
+            return;
+        }
+        buf[..frame.len()].copy_from_slice(frame);
+        self.rx_runner.rx_done(frame.len());
+    }
+    /// takes the sniffer and the scratch on their own, the receive buffer is still borrowed
+    /// from the runner
+    fn sniff(
+        sniffer: Option<&'static SnifferChannel>,
+        result: Result<usize, ReadError>,
+        scratch: &[u8],
+    ) {
+        let Some(sniffer) = sniffer else {
+            return;
+        };
+        // the sniffer holds frames of the default size
+        let result = result.map(|len| len.min(BUS_FRAME_SIZE));
+        let mut frame = SniffedFrame {
+            timestamp: Instant::now(),
+            result,
+            data: [0; BUS_FRAME_SIZE],
+        };
+        if let Ok(len) = result {
+            frame.data[..len].copy_from_slice(&scratch[..len]);
+        }
+        if sniffer.try_send(frame).is_err() {
+            info!("sniffer channel full, dropping frame");
+        }
+    }
+    /// takes the filter and address on their own, the frame is still borrowed from the runner
+    fn is_for_us(
+        filter: Option<&AddressFilter>,
+        address: MacAddress,
+        destination: Option<MacAddress>,
+    ) -> bool {
+        let Some(filter) = filter else {
+            return true;
+        };
+        match destination {
+            Some(destination) => filter.accepts(address, destination),
+            None => false,
+        }
+    }
+    /// reads the next burst once the stack has room for it
+    pub(crate) async fn read(&mut self, shared: &LinkShared) -> LinkEvent {
+        self.rx_runner.rx_buf().await;
+        let r = self.read.read_until_idle(&mut self.scratch).await;
+        self.on_read(r, shared).await
+    }
+
+    /// like [`Self::read`], but hands the local replies to the stack while the read runs
+    /// instead of cutting it short
+    pub(crate) async fn read_with_replies(
+        &mut self,
+        shared: &LinkShared,
+        replies: &LocalReplies,
+    ) -> LinkEvent {
+        self.rx_runner.rx_buf().await;
+        let read = self.read.read_until_idle(&mut self.scratch);
+        let r = match select(read, Self::deliver_replies(&mut self.rx_runner, replies)).await {
+            Either::First(r) => r,
+            Either::Second(never) => never,
+        };
+        self.on_read(r, shared).await
+    }
+
+    /// takes the receive slot before the reply, dropping this at either await loses nothing
+    async fn deliver_replies(rx_runner: &mut RxRunner<'static, MTU>, replies: &LocalReplies) -> ! {
+        loop {
+            let buf = rx_runner.rx_buf().await;
+            let reply = replies.recv().await;
+            if reply.len() > buf.len() {
+                info!("local reply does not fit the mtu");
+                continue;
+            }
+            buf[..reply.len()].copy_from_slice(&reply);
+            rx_runner.rx_done(reply.len());
+        }
+    }
+
+    /// takes the receive slot again, it was free before the read unless a local reply took it
+    async fn on_read(&mut self, r: Result<usize, ReadError>, shared: &LinkShared) -> LinkEvent {
+        let buf = self.rx_runner.rx_buf().await;
+        Self::sniff(self.sniffer, r, &self.scratch);
+        match r {
+            Ok(s) => {
+                if shared.echo.is_echoed_burst(&self.scratch[..s]) {
+                    // our own burst tells nothing about the other nodes
+                    self.stats.echo_suppressed();
+                    return LinkEvent::Nothing;
+                }
+                if let Some(polling) = shared.polling.as_ref() {
+                    if let Some(frame) = PollFrame::parse(&self.scratch[..s]) {
+                        polling.on_frame(frame);
+                        return LinkEvent::Traffic;
+                    }
+                }
+                if let Some(token) = shared.token.as_ref() {
+                    let control = ControlFrame::parse(&self.scratch[..s]);
+                    token.on_burst(control);
+                    if control.is_some() {
+                        return LinkEvent::Traffic;
+                    }
+                }
+                if let Some(tdma) = shared.tdma.as_ref() {
+                    if is_beacon(&self.scratch[..s]) {
+                        tdma.on_beacon_received();
+                        self.stats.beacon_received();
+                        return LinkEvent::Traffic;
+                    }
+                }
+                if let Some(jam) = self.jam {
+                    if jam.is_jam(&self.scratch[..s]) {
+                        info!("jam on the bus, dropping...");
+                        self.stats.jam_received();
+                        shared.on_jam(&jam);
+                        return LinkEvent::Traffic;
+                    }
+                }
+                let start = if self.arbitration {
+                    if s < ARBITRATION_FIELD_SIZE {
+                        info!("read lost, no arbitration field...");
+                        self.stats.rx_lost();
+                        self.stats.framing_error();
+                        return LinkEvent::FramingError;
+                    }
+                    ARBITRATION_FIELD_SIZE
+                } else {
+                    0
+                };
+                let frame: &[u8] = match self.reassembler.as_mut() {
+                    Some(reassembler) => match reassembler.receive(&self.scratch[start..s]) {
+                        Reassembled::Complete(frame) => frame,
+                        Reassembled::Incomplete => return LinkEvent::Traffic,
+                        Reassembled::Dropped => {
+                            self.stats.reassembly_failure();
+                            return LinkEvent::Traffic;
+                        }
+                        Reassembled::Malformed => {
+                            info!("read lost, malformed fragment...");
+                            self.stats.rx_lost();
+                            self.stats.framing_error();
+                            return LinkEvent::FramingError;
+                        }
+                    },
+                    None => &self.scratch[start..s],
+                };
+                let payload = match self.arq.as_mut() {
+                    Some(arq) => match arq.receive(frame, &shared.arq) {
+                        Received::Deliver(payload) => payload,
+                        Received::Duplicate => {
+                            self.stats.duplicate_frame();
+                            return LinkEvent::Traffic;
+                        }
+                        Received::Consumed => return LinkEvent::Traffic,
+                        Received::Malformed => {
+                            info!("read lost, unknown link header...");
+                            self.stats.rx_lost();
+                            self.stats.framing_error();
+                            return LinkEvent::FramingError;
+                        }
+                    },
+                    None => 0..frame.len(),
+                };
+                let frame = &frame[payload];
+                // sorted out on the link header, before anything is translated or decompressed
+                let addresses = self.medium.link_addresses(frame);
+                if let Some((destination, source)) = addresses {
+                    if shared.echo.is_echoed_frame(source, self.address) {
+                        self.stats.echo_suppressed();
+                        return LinkEvent::Nothing;
+                    }
+                    if !Self::is_for_us(self.filter.as_ref(), self.address, Some(destination)) {
+                        self.stats.frame_filtered();
+                        return LinkEvent::Traffic;
+                    }
+                }
+                let Some(len) = self.medium.bus_to_stack(frame, buf) else {
+                    info!("read lost, frame does not fit the medium...");
+                    self.stats.rx_lost();
+                    self.stats.overflow_error();
+                    return LinkEvent::Traffic;
+                };
+                // the ip medium has no link header, its group destinations only show up
+                // in the translated frame
+                let translated =
+                    ethernet_addresses(&buf[..len]).map(|(destination, _)| destination);
+                if addresses.is_none()
+                    && !Self::is_for_us(self.filter.as_ref(), self.address, translated)
+                {
+                    self.stats.frame_filtered();
+                    return LinkEvent::Traffic;
+                }
+                self.stats.frame_received();
+                self.rx_runner.rx_done(len);
+                LinkEvent::Traffic
+            }
+            Err(err) => {
+                info!("read lost...");
+                self.stats.rx_lost();
+                if err != ReadError::OverflowError && shared.in_jam_window() {
+                    // the jam garbled whatever overlapped it
+                    self.stats.collision();
+                    return LinkEvent::Traffic;
+                }
+                match err {
+                    ReadError::OverflowError => {
+                        self.stats.overflow_error();
+                        LinkEvent::Traffic
+                    }
+                    ReadError::ChecksumError => {
+                        self.stats.checksum_error();
+                        LinkEvent::FramingError
+                    }
+                    _ => {
+                        self.stats.framing_error();
+                        LinkEvent::FramingError
+                    }
+                }
+            }
         }
     }
 }
 
-pub struct AsyncHalfDuplexUart<R, W, T, RN>
-where
+/// the frame sizes default to a full ethernet frame, `BUS` has to be at least
+/// [`bus_frame_size`] of `MTU`. Use `<AsyncHalfDuplexUart<_, _, _, _, _>>::new` to get the defaults
+pub struct AsyncHalfDuplexUart<
+    R,
+    W,
+    T,
+    RN,
+    P = BinaryExponential,
+    const MTU: usize = IP_FRAME_SIZE,
+    const BUS: usize = BUS_FRAME_SIZE,
+> where
     R: Read,
     W: Write,
     T: AsyncTimer,
     RN: RngCore,
+    P: BackoffPolicy,
 {
-    tx_handler: TxHandler<T, W, RN>,
-    rx_handler: RxHandler<R>,
-    state: StateRunner<'static>,
+    tx_handler: TxHandler<T, W, RN, P, MTU, BUS>,
+    rx_handler: RxHandler<R, MTU, BUS>,
+    link: LinkMonitor,
+    stats: Option<&'static LinkCounters>,
+    shared: LinkShared,
+    listen_only: bool,
 }
 
-impl<R, W, T, RN> AsyncHalfDuplexUart<R, W, T, RN>
+impl<R, W, T, RN, P, const MTU: usize, const BUS: usize>
+    AsyncHalfDuplexUart<R, W, T, RN, P, MTU, BUS>
 where
     R: Read,
     W: Write,
     T: AsyncTimer,
     RN: RngCore,
+    P: BackoffPolicy,
 {
     pub fn new(
         read: R,
         write: W,
         timer: T,
-        runner: Runner<'static, IP_FRAME_SIZE>,
-        rng: RN,
+        runner: Runner<'static, MTU>,
+        mut rng: RN,
+        policy: P,
+        config: HalfDuplexConfig,
     ) -> Self {
+        let scheduled = config.tdma.is_some() as usize
+            + config.token.is_some() as usize
+            + config.polling.is_some() as usize;
+        assert!(
+            scheduled == 0 || (scheduled == 1 && config.arq.is_none()),
+            "tdma, token passing and polling cannot be combined with arq or each other"
+        );
+        assert!(
+            config.arq.is_none() || config.medium != LinkMedium::Ip,
+            "arq needs the ethernet or the node medium"
+        );
+        assert!(
+            config.arbitration.is_none()
+                || (scheduled == 0 && config.arq.is_none() && config.fragmentation.is_none()),
+            "arbitration cannot be combined with arq, fragmentation or the scheduled modes"
+        );
+        let arbiter = config
+            .arbitration
+            .map(|arbitration| Arbiter::new(arbitration, config.address));
+        let id = || {
+            node_id(config.address)
+                .filter(|&id| id != BROADCAST_NODE)
+                .expect("token passing and polling need a node mac address")
+        };
+        let token = config.token.map(|token| TokenState::new(token, id()));
+        let polling = config.polling.map(|polling| PollState::new(polling, id()));
+        let queues = config
+            .priority
+            .map(|priority| PriorityQueues::new(priority, MTU));
         let (state, rx, tx) = runner.split();
+        let stats = StatsRecorder::new(config.stats);
+        let medium = MediumAdapter::new(config.medium, config.address, config.compression);
+        let arq_receiver = config
+            .arq
+            .as_ref()
+            .map(|_| ArqReceiver::new(config.address, medium.bus_addresses()));
+        let arq_sender = config.arq.map(|arq| ArqSender::new(arq, config.address));
+        let fragmenter = config
+            .fragmentation
+            .as_ref()
+            .map(|fragmentation| Fragmenter::new(fragmentation, rng.next_u32() as u16, BUS));
+        let reassembler = config.fragmentation.as_ref().map(Reassembler::new);
+        let max_burst = match config.fragmentation.as_ref() {
+            Some(fragmentation) => fragmentation.fragment_size.min(MAX_FRAGMENT_SIZE),
+            None => BUS,
+        };
         return Self {
-            tx_handler: TxHandler::new(timer, write, tx, rng),
-            rx_handler: RxHandler::new(read, rx),
-            state,
+            tx_handler: TxHandler::new(
+                write,
+                tx,
+                BackoffHandler::new(timer, rng, policy),
+                TxOptions {
+                    outcomes: config.outcomes,
+                    stats,
+                    medium,
+                    arq: arq_sender,
+                    fragmenter,
+                    jam: config.jam,
+                    arbiter,
+                    queues,
+                },
+            ),
+            rx_handler: RxHandler::new(read, rx)
+                .with_stats(stats)
+                .with_medium(medium)
+                .with_filter(config.address_filter, config.address)
+                .with_sniffer(config.sniffer)
+                .with_jam(config.jam)
+                .with_arq(arq_receiver)
+                .with_arbitration(arbiter.is_some())
+                .with_reassembler(reassembler),
+            link: LinkMonitor::new(
+                state,
+                config.link_down_after_framing_errors,
+                config.silence_timeout,
+            ),
+            stats: config.stats,
+            shared: LinkShared::new(
+                config.echo_suppression,
+                config.tdma.map(|tdma| TdmaSchedule::new(tdma, max_burst)),
+                token,
+                polling,
+                config.max_scheduled_attempts,
+            ),
+            listen_only: config.listen_only,
         };
     }
 
+    /// handle to the link counters, `None` if the config did not provide any
+    pub fn stats(&self) -> Option<LinkStatsHandle> {
+        self.stats.map(LinkStatsHandle::new)
+    }
+
     pub async fn start(&mut self) -> ! {
+        self.link.start();
         loop {
-            select(self.tx_handler.transmit(), self.rx_handler.read()).await;
+            let listen_only = self.listen_only;
+            let tx_handler = &mut self.tx_handler;
+            let shared = &self.shared;
+            let transmit = async move {
+                if listen_only {
+                    tx_handler.discard().await
+                } else {
+                    tx_handler.transmit(shared).await
+                }
+            };
+            let result = select3(
+                transmit,
+                self.rx_handler.read(&self.shared),
+                self.link.silence(),
+            )
+            .await;
+            match result {
+                Either3::First(event) => self.link.on_transmit_event(event),
+                Either3::Second(event) => self.link.on_event(event),
+                Either3::Third(()) => self.link.on_silence(),
+            }
+            if let Some(reply) = self.shared.local_reply.take() {
+                self.rx_handler.deliver_local(&reply).await;
+            }
         }
     }
 }
 
-impl<R, W, T, RN> AsyncDevice for AsyncHalfDuplexUart<R, W, T, RN>
+impl<R, W, T, RN, P, const MTU: usize, const BUS: usize> AsyncDevice
+    for AsyncHalfDuplexUart<R, W, T, RN, P, MTU, BUS>
 where
     R: Read,
     W: Write,
     T: AsyncTimer,
     RN: RngCore,
+    P: BackoffPolicy,
 {
     async fn start(&mut self) -> ! {
         AsyncHalfDuplexUart::start(self).await
     }
 }
 
+/// largest frame on the bus for frames of `mtu` bytes from the stack
+pub const fn bus_frame_size(mtu: usize) -> usize {
+    mtu + LINK_HEADER_SIZE
+}
+
 pub const IP_FRAME_SIZE: usize = 1048;
-const CHANNEL_SIZE: usize = 10;
-const TRANSMIT_CHANNEL_SIZE: usize = CHANNEL_SIZE;
-const RECEIVE_SENDER_SIZE: usize = CHANNEL_SIZE;
+/// largest frame on the bus: an ip frame plus the link header
+pub const BUS_FRAME_SIZE: usize = bus_frame_size(IP_FRAME_SIZE);
+const LINK_HEADER_SIZE: usize = ARQ_HEADER_SIZE + ARBITRATION_FIELD_SIZE;
+const MIN_IDLE_POLL: Duration = Duration::from_micros(50);
+pub const CHANNEL_SIZE: usize = 10;
+pub const TRANSMIT_CHANNEL_SIZE: usize = CHANNEL_SIZE;
+pub const RECEIVE_SENDER_SIZE: usize = CHANNEL_SIZE;
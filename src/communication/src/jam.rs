@@ -0,0 +1,34 @@
+//! after a collision the transmitter puts a jam pattern on the bus, so every node that was
+//! sending at the same time sees its frame corrupted and backs off as well. Framing errors
+//! right after a jam was sent or read are counted as collisions, they do not take the
+//! link down
+use embassy_time::Duration;
+
+#[derive(Clone, Copy)]
+pub struct JamConfig {
+    /// sent right after a collision without waiting for the line, every node on the bus
+    /// must agree on this
+    pub pattern: &'static [u8],
+    /// framing errors read this long after a jam are blamed on the jam
+    pub window: Duration,
+}
+
+impl Default for JamConfig {
+    fn default() -> Self {
+        Self {
+            pattern: &[0xA5, 0x5A, 0xA5, 0x5A, 0xA5, 0x5A, 0xA5, 0x5A],
+            window: Duration::from_millis(2),
+        }
+    }
+}
+
+impl JamConfig {
+    /// the jam goes out raw right into whatever else is on the line, so it shows up
+    /// anywhere in a burst, most often behind the remains of a frame
+    pub(crate) fn is_jam(&self, burst: &[u8]) -> bool {
+        !self.pattern.is_empty()
+            && burst
+                .windows(self.pattern.len())
+                .any(|window| window == self.pattern)
+    }
+}
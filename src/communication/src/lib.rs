@@ -6,11 +6,34 @@
 use defmt::*;
 use embassy_net_driver::Driver;
 
+pub mod address_filter;
+pub mod arbitration;
+pub mod arq;
+pub mod backoff;
+pub mod carrier_sense;
+pub mod cobs;
+pub mod compression;
+pub mod control;
+pub mod echo;
+pub mod fcs;
+pub mod fragment;
+pub mod full_duplex;
 pub mod half_duplex;
+pub mod jam;
+pub mod link_state;
+pub mod medium;
+pub mod outcome;
+pub mod polling;
+pub mod priority;
+pub mod sniffer;
+pub mod stats;
+pub mod tdma;
+pub mod token;
 use core::future::Future;
 use embassy_net::Stack;
 use embassy_time::Duration;
 
+use backoff::BackoffPolicy;
 use rand_core::RngCore;
 pub trait AsyncTimer {
     type AsyncOutput<'a>: Future<Output = ()> + 'a
@@ -31,20 +54,54 @@ pub trait CoreServiceLocator {
 pub enum WriteError {
     FramingError,
     CollisionError,
+    /// the echo of the arbitration field differed, a more urgent frame has the bus
+    ArbitrationLost,
 }
 pub trait Write {
     async fn write<'a>(&'a mut self, buf: &'a [u8]) -> Result<(), WriteError>
     where
         Self: Sized;
+
+    /// writes a frame that starts with `arbitration` bytes of arbitration field. The echo of
+    /// those is compared byte by byte and the write stops with [`WriteError::ArbitrationLost`]
+    /// as soon as it differs
+    async fn write_arbitrated<'a>(
+        &'a mut self,
+        buf: &'a [u8],
+        arbitration: usize,
+    ) -> Result<(), WriteError>
+    where
+        Self: Sized,
+    {
+        let _ = arbitration;
+        self.write(buf).await
+    }
+
+    /// puts `buf` on the line as it is, without framing and without checking the echo.
+    /// Used for the jam, which has to go out even while the line is garbled
+    async fn write_raw<'a>(&'a mut self, buf: &'a [u8]) -> Result<(), WriteError>
+    where
+        Self: Sized,
+    {
+        self.write(buf).await
+    }
     fn is_line_free(&self) -> bool;
+
+    /// how long until the line is expected to be free again, used to pace the
+    /// transmitter while it waits for an idle line
+    fn time_until_free(&self) -> Duration {
+        Duration::from_ticks(0)
+    }
 }
 
-#[derive(Debug, Format)]
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
 #[non_exhaustive]
 #[allow(dead_code)]
 pub enum ReadError {
     FramingError,
     OverflowError,
+    /// the frame arrived but its frame check sequence did not match
+    ChecksumError,
 }
 
 pub trait Read {
@@ -79,18 +136,24 @@ impl BackoffState {
     }
 }
 
-pub struct BackoffHandler<T: AsyncTimer, R: RngCore> {
+pub struct BackoffHandler<T: AsyncTimer, R: RngCore, P: BackoffPolicy> {
     timer: T,
     rng: R,
+    policy: P,
     state: BackoffState,
 }
 
-impl<T: AsyncTimer, R: RngCore> BackoffHandler<T, R> {
-    pub fn new(timer: T, rng: R) -> Self {
+impl<T: AsyncTimer, R: RngCore, P: BackoffPolicy> BackoffHandler<T, R, P> {
+    pub fn new(timer: T, rng: R, policy: P) -> Self {
+        let state = BackoffState {
+            max_backoffs: policy.max_backoffs(),
+            ..Default::default()
+        };
         Self {
             timer,
             rng,
-            state: Default::default(),
+            policy,
+            state,
         }
     }
 
@@ -105,7 +168,7 @@ impl<T: AsyncTimer, R: RngCore> BackoffHandler<T, R> {
             let to_wait = self.calculate_backoff();
             info!("waiting: {:?}", &to_wait);
             self.timer
-                .duration(Duration::from_micros(to_wait as u64))
+                .duration(to_wait)
                 .expect("could not start backoff timer!");
             return Ok(());
         }
@@ -119,17 +182,9 @@ impl<T: AsyncTimer, R: RngCore> BackoffHandler<T, R> {
         Err(())
     }
 
-    pub fn calculate_backoff(&mut self) -> usize {
-        return self.exponential_component() + self.random_component() as usize;
-    }
-    fn exponential_component(&self) -> usize {
-        const ONE_MS: usize = 1000;
-        return ONE_MS << self.state.number_backoffs_attempted;
-    }
-
-    fn random_component(&mut self) -> u8 {
-        let res = self.rng.next_u64();
-        return res as u8;
+    pub fn calculate_backoff(&mut self) -> Duration {
+        self.policy
+            .backoff_duration(self.state.number_backoffs_attempted, &mut self.rng)
     }
 
     pub fn clear(&mut self) {
@@ -137,13 +192,21 @@ impl<T: AsyncTimer, R: RngCore> BackoffHandler<T, R> {
     }
 }
 
+/// lets the tests link on the host, the firmware brings its own logger
 #[cfg(test)]
-mod tests {
-    use super::*;
+mod host {
+    #[defmt::global_logger]
+    struct Logger;
+
+    unsafe impl defmt::Logger for Logger {
+        fn acquire() {}
+        unsafe fn flush() {}
+        unsafe fn release() {}
+        unsafe fn write(_bytes: &[u8]) {}
+    }
 
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+    #[defmt::panic_handler]
+    fn panic() -> ! {
+        core::panic!("defmt panic")
     }
 }
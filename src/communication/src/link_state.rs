@@ -0,0 +1,116 @@
+use core::future;
+
+use defmt::*;
+use embassy_net_driver::LinkState;
+use embassy_net_driver_channel::StateRunner;
+use embassy_time::{Duration, Instant, Timer};
+
+/// what a transmit or receive attempt tells us about the health of the bus
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum LinkEvent {
+    /// nothing happened on the bus, e.g. we only waited for a backoff
+    Nothing,
+    /// a frame went out or came in, even a collision proves someone is there
+    Traffic,
+    FramingError,
+}
+
+/// drives the link state the stack sees from the outcome of bus accesses.
+/// The link goes down after `max_framing_errors` framing errors in a row or after
+/// `silence_timeout` without received traffic, and comes back up with the next frame read.
+/// Our own transmits never count as traffic, a node alone on the bus hears its echo
+pub struct LinkMonitor {
+    state: StateRunner<'static>,
+    max_framing_errors: Option<usize>,
+    silence_timeout: Option<Duration>,
+    consecutive_framing_errors: usize,
+    last_traffic: Instant,
+    up: bool,
+}
+
+impl LinkMonitor {
+    pub fn new(
+        state: StateRunner<'static>,
+        max_framing_errors: Option<usize>,
+        silence_timeout: Option<Duration>,
+    ) -> Self {
+        Self {
+            state,
+            max_framing_errors,
+            silence_timeout,
+            consecutive_framing_errors: 0,
+            last_traffic: Instant::now(),
+            up: false,
+        }
+    }
+
+    fn set_up(&mut self, up: bool) {
+        if self.up == up {
+            return;
+        }
+        info!("link {}", if up { "up" } else { "down" });
+        self.up = up;
+        self.state
+            .set_link_state(if up { LinkState::Up } else { LinkState::Down });
+    }
+
+    /// the link starts out up so that the stack may send the frames that prove it
+    pub fn start(&mut self) {
+        self.last_traffic = Instant::now();
+        self.set_up(true);
+    }
+
+    pub fn is_up(&self) -> bool {
+        self.up
+    }
+
+    /// an event from the receiver
+    pub fn on_event(&mut self, event: LinkEvent) {
+        match event {
+            LinkEvent::Nothing => {}
+            LinkEvent::Traffic => {
+                self.consecutive_framing_errors = 0;
+                self.last_traffic = Instant::now();
+                self.set_up(true);
+            }
+            LinkEvent::FramingError => {
+                self.consecutive_framing_errors += 1;
+                if let Some(max) = self.max_framing_errors {
+                    if self.consecutive_framing_errors >= max {
+                        self.set_up(false);
+                    }
+                }
+            }
+        }
+    }
+
+    /// an event from the transmitter, a frame sent only clears the framing errors
+    pub fn on_transmit_event(&mut self, event: LinkEvent) {
+        match event {
+            LinkEvent::Traffic => self.consecutive_framing_errors = 0,
+            event => self.on_event(event),
+        }
+    }
+
+    /// resolves once the bus has been silent for the silence timeout while the link is up
+    pub async fn silence(&self) {
+        match self.silence_deadline() {
+            Some(deadline) => Timer::at(deadline).await,
+            None => future::pending().await,
+        }
+    }
+
+    /// when the link goes down for silence unless a frame is read before, `None` while
+    /// the link is down or without a silence timeout
+    pub fn silence_deadline(&self) -> Option<Instant> {
+        match self.silence_timeout {
+            Some(timeout) if self.up => Some(self.last_traffic + timeout),
+            _ => None,
+        }
+    }
+
+    pub fn on_silence(&mut self) {
+        info!("no traffic on the bus for {:?}", self.silence_timeout);
+        self.set_up(false);
+    }
+}
@@ -0,0 +1,501 @@
+//! what the frames on the bus look like compared to the ethernet frames the stack exchanges
+//! with `embassy_net_driver_channel`.
+//!
+//! The channel only emulates ethernet, so the ip medium is implemented at this boundary:
+//! only `[type][ip packet]` goes on the bus, arp requests of the stack are answered locally
+//! and received packets get a synthesized ethernet header.
+//!
+//! The node medium keeps every ethertype but replaces both mac addresses by one byte
+//! node ids, a bus frame is `[type][destination][source][payload]`. Every node on the bus
+//! has to use the mac address [`node_mac`] of its id.
+use crate::compression::{CompressionConfig, LinkNodes};
+
+use defmt::*;
+
+pub type MacAddress = [u8; 6];
+
+pub const ETHERNET_HEADER_SIZE: usize = 14;
+pub(crate) const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_ARP: u16 = 0x0806;
+pub(crate) const ETHERTYPE_IPV6: u16 = 0x86DD;
+
+/// the ethertype follows as two bytes
+const TYPE_OTHER: u8 = 0x00;
+const TYPE_IPV4: u8 = 0x04;
+/// an ipv4 packet with compressed headers, see [`crate::compression`]
+const TYPE_IPV4_COMPRESSED: u8 = 0x05;
+const TYPE_IPV6: u8 = 0x06;
+const TYPE_ARP: u8 = 0x08;
+
+const NODE_HEADER_SIZE: usize = 3;
+/// node id that addresses every node, ethernet broadcast and multicast map to it
+pub const BROADCAST_NODE: u8 = 0xFF;
+pub const BROADCAST_MAC: MacAddress = [0xFF; 6];
+const NODE_MAC_PREFIX: [u8; 5] = [0x02, 0x00, 0x00, 0x00, 0x00];
+
+pub const ARP_FRAME_SIZE: usize = ETHERNET_HEADER_SIZE + 28;
+
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LinkMedium {
+    /// the ethernet frames of the stack go on the bus unchanged
+    #[default]
+    Ethernet,
+    /// only ip packets go on the bus, behind a one byte type field. Every node must use this
+    Ip,
+    /// ethernet frames with one byte node ids instead of mac addresses. Every node must
+    /// use this and a mac address from [`node_mac`]
+    Node,
+}
+
+/// mac address of a node on a bus with the node medium, locally administered
+pub const fn node_mac(id: u8) -> MacAddress {
+    [0x02, 0x00, 0x00, 0x00, 0x00, id]
+}
+
+/// node id of a mac address, `None` if the address has no node id
+pub fn node_id(mac: MacAddress) -> Option<u8> {
+    if mac == BROADCAST_MAC || mac[0] & 0x01 != 0 {
+        return Some(BROADCAST_NODE);
+    }
+    if mac[0..5] != NODE_MAC_PREFIX || mac[5] == BROADCAST_NODE {
+        return None;
+    }
+    Some(mac[5])
+}
+
+fn mac_for_node(id: u8) -> MacAddress {
+    if id == BROADCAST_NODE {
+        return BROADCAST_MAC;
+    }
+    node_mac(id)
+}
+
+/// source and destination of a frame of the node medium
+fn node_addresses(frame: &[u8]) -> Option<(MacAddress, MacAddress)> {
+    if frame.len() < NODE_HEADER_SIZE {
+        return None;
+    }
+    Some((mac_for_node(frame[1]), mac_for_node(frame[2])))
+}
+
+/// mac address the ip medium hands to the stack for an ipv4 address, locally administered
+pub fn mac_for_ipv4(ip: [u8; 4]) -> MacAddress {
+    [0x02, 0x00, ip[0], ip[1], ip[2], ip[3]]
+}
+
+/// ipv6 link local address of a node, the interface id is the modified EUI-64 of its mac address
+pub const fn link_local_ipv6(mac: MacAddress) -> [u8; 16] {
+    [
+        0xFE,
+        0x80,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        mac[0] ^ 0x02,
+        mac[1],
+        mac[2],
+        0xFF,
+        0xFE,
+        mac[3],
+        mac[4],
+        mac[5],
+    ]
+}
+
+/// ethernet group address of the solicited node multicast group of an ipv6 address
+pub const fn solicited_node_mac(ip: [u8; 16]) -> MacAddress {
+    [0x33, 0x33, 0xFF, ip[13], ip[14], ip[15]]
+}
+
+/// mac address behind an ipv6 address, exact for EUI-64 interface ids
+fn mac_for_ipv6(ip: &[u8]) -> MacAddress {
+    let interface = &ip[8..16];
+    if interface[3..5] == [0xFF, 0xFE] {
+        return [
+            interface[0] ^ 0x02,
+            interface[1],
+            interface[2],
+            interface[5],
+            interface[6],
+            interface[7],
+        ];
+    }
+    mac_for_ipv4([ip[12], ip[13], ip[14], ip[15]])
+}
+
+/// ethernet group address of an ip multicast destination, `None` for anything else
+pub fn multicast_mac(ethertype: u16, packet: &[u8]) -> Option<MacAddress> {
+    match ethertype {
+        ETHERTYPE_IPV4 if packet.len() >= 20 && packet[16] & 0xF0 == 0xE0 => {
+            Some([0x01, 0x00, 0x5E, packet[17] & 0x7F, packet[18], packet[19]])
+        }
+        ETHERTYPE_IPV6 if packet.len() >= 40 && packet[24] == 0xFF => {
+            Some([0x33, 0x33, packet[36], packet[37], packet[38], packet[39]])
+        }
+        _ => None,
+    }
+}
+
+/// ethertype of an ethernet frame, at least a header long
+pub(crate) fn ethertype(frame: &[u8]) -> u16 {
+    u16::from_be_bytes([frame[12], frame[13]])
+}
+
+fn write_ethernet_header(
+    out: &mut [u8],
+    destination: MacAddress,
+    source: MacAddress,
+    ethertype: u16,
+) {
+    out[0..6].copy_from_slice(&destination);
+    out[6..12].copy_from_slice(&source);
+    out[12..14].copy_from_slice(&ethertype.to_be_bytes());
+}
+
+/// source and destination of an ethernet frame
+pub(crate) fn ethernet_addresses(frame: &[u8]) -> Option<(MacAddress, MacAddress)> {
+    if frame.len() < ETHERNET_HEADER_SIZE {
+        return None;
+    }
+    let mut destination = [0; 6];
+    let mut source = [0; 6];
+    destination.copy_from_slice(&frame[0..6]);
+    source.copy_from_slice(&frame[6..12]);
+    Some((destination, source))
+}
+
+/// a frame the stack wants to send
+pub(crate) enum Outbound {
+    /// goes on the bus, the length of the translated frame
+    Bus(usize),
+    /// answered without touching the bus, the reply goes back to the stack
+    Local([u8; ARP_FRAME_SIZE]),
+    /// cannot be carried by this medium
+    Drop,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct MediumAdapter {
+    medium: LinkMedium,
+    address: MacAddress,
+    compression: Option<CompressionConfig>,
+}
+
+impl MediumAdapter {
+    pub fn new(
+        medium: LinkMedium,
+        address: MacAddress,
+        compression: Option<CompressionConfig>,
+    ) -> Self {
+        if medium == LinkMedium::Node {
+            assert!(
+                matches!(node_id(address), Some(id) if id != BROADCAST_NODE),
+                "the node medium needs a node mac address"
+            );
+        }
+        assert!(
+            compression.is_none() || medium != LinkMedium::Ethernet,
+            "header compression needs the ip or the node medium"
+        );
+        Self {
+            medium,
+            address,
+            compression,
+        }
+    }
+
+    fn compress(&self, packet: &[u8], link: Option<LinkNodes>, out: &mut [u8]) -> Option<usize> {
+        self.compression.as_ref()?.compress(packet, link, out)
+    }
+
+    /// decompresses into the ip part of `out` and writes the ethernet header in front
+    fn decompress(
+        &self,
+        compressed: &[u8],
+        link: Option<LinkNodes>,
+        destination: MacAddress,
+        source: Option<MacAddress>,
+        out: &mut [u8],
+    ) -> Option<usize> {
+        let packet = &mut out[ETHERNET_HEADER_SIZE..];
+        let len = self
+            .compression
+            .as_ref()?
+            .decompress(compressed, link, packet)?;
+        let source = source.unwrap_or_else(|| {
+            let mut ip = [0; 4];
+            ip.copy_from_slice(&packet[12..16]);
+            mac_for_ipv4(ip)
+        });
+        let destination = self.group_destination(destination, ETHERTYPE_IPV4, packet);
+        write_ethernet_header(out, destination, source, ETHERTYPE_IPV4);
+        Some(ETHERNET_HEADER_SIZE + len)
+    }
+
+    /// the bus only knows broadcast, ip multicast gets its ethernet group address back
+    /// so the stack sees the frame as it was sent
+    fn group_destination(
+        &self,
+        destination: MacAddress,
+        ethertype: u16,
+        packet: &[u8],
+    ) -> MacAddress {
+        let is_group = destination == BROADCAST_MAC || self.medium == LinkMedium::Ip;
+        match multicast_mac(ethertype, packet) {
+            Some(group) if is_group => group,
+            _ => destination,
+        }
+    }
+
+    /// unicast destination of a frame from the stack, `None` for group addresses and
+    /// for media that carry no addresses
+    pub fn unicast_destination(&self, frame: &[u8]) -> Option<MacAddress> {
+        match self.medium {
+            LinkMedium::Ethernet | LinkMedium::Node => {
+                let (destination, _) = ethernet_addresses(frame)?;
+                if destination[0] & 0x01 == 0 {
+                    Some(destination)
+                } else {
+                    None
+                }
+            }
+            LinkMedium::Ip => None,
+        }
+    }
+
+    /// reads destination and source from a frame as it is on the bus
+    pub fn bus_addresses(&self) -> fn(&[u8]) -> Option<(MacAddress, MacAddress)> {
+        match self.medium {
+            LinkMedium::Ethernet => ethernet_addresses,
+            LinkMedium::Ip => |_| None,
+            LinkMedium::Node => node_addresses,
+        }
+    }
+
+    /// translates a frame of the stack to how it goes on the bus
+    pub fn stack_to_bus(&self, frame: &[u8], out: &mut [u8]) -> Outbound {
+        if frame.len() < ETHERNET_HEADER_SIZE {
+            return Outbound::Drop;
+        }
+        match self.medium {
+            LinkMedium::Ethernet => {
+                if frame.len() > out.len() {
+                    return Outbound::Drop;
+                }
+                out[..frame.len()].copy_from_slice(frame);
+                Outbound::Bus(frame.len())
+            }
+            LinkMedium::Ip => {
+                let kind = match ethertype(frame) {
+                    ETHERTYPE_IPV4 => TYPE_IPV4,
+                    ETHERTYPE_IPV6 => TYPE_IPV6,
+                    ETHERTYPE_ARP => {
+                        return match self.arp_reply(frame) {
+                            Some(reply) => Outbound::Local(reply),
+                            None => Outbound::Drop,
+                        }
+                    }
+                    _ => return Outbound::Drop,
+                };
+                let packet = &frame[ETHERNET_HEADER_SIZE..];
+                if kind == TYPE_IPV4 {
+                    if let Some(compressed) = self.compress(packet, None, &mut out[1..]) {
+                        out[0] = TYPE_IPV4_COMPRESSED;
+                        return Outbound::Bus(1 + compressed);
+                    }
+                }
+                if packet.len() + 1 > out.len() {
+                    return Outbound::Drop;
+                }
+                out[0] = kind;
+                out[1..packet.len() + 1].copy_from_slice(packet);
+                Outbound::Bus(packet.len() + 1)
+            }
+            LinkMedium::Node => {
+                let Some((destination, source)) = ethernet_addresses(frame) else {
+                    return Outbound::Drop;
+                };
+                let addresses = (node_id(destination), node_id(source));
+                let (Some(destination), Some(source)) = addresses else {
+                    info!("frame between non node addresses, dropping...");
+                    return Outbound::Drop;
+                };
+                let ethertype = ethertype(frame);
+                let header = match ethertype {
+                    ETHERTYPE_IPV4 | ETHERTYPE_IPV6 | ETHERTYPE_ARP => NODE_HEADER_SIZE,
+                    _ => NODE_HEADER_SIZE + 2,
+                };
+                let payload = &frame[ETHERNET_HEADER_SIZE..];
+                out[1] = destination;
+                out[2] = source;
+                if ethertype == ETHERTYPE_IPV4 {
+                    let link = Some(LinkNodes {
+                        destination,
+                        source,
+                    });
+                    let compressed = self.compress(payload, link, &mut out[NODE_HEADER_SIZE..]);
+                    if let Some(compressed) = compressed {
+                        out[0] = TYPE_IPV4_COMPRESSED;
+                        return Outbound::Bus(NODE_HEADER_SIZE + compressed);
+                    }
+                }
+                if header + payload.len() > out.len() {
+                    return Outbound::Drop;
+                }
+                out[0] = match ethertype {
+                    ETHERTYPE_IPV4 => TYPE_IPV4,
+                    ETHERTYPE_IPV6 => TYPE_IPV6,
+                    ETHERTYPE_ARP => TYPE_ARP,
+                    _ => TYPE_OTHER,
+                };
+                if header > NODE_HEADER_SIZE {
+                    out[3..5].copy_from_slice(&ethertype.to_be_bytes());
+                }
+                out[header..header + payload.len()].copy_from_slice(payload);
+                Outbound::Bus(header + payload.len())
+            }
+        }
+    }
+
+    /// destination and source of a frame from the bus as its link header has them, `None`
+    /// on the ip medium, which has no link header
+    pub fn link_addresses(&self, frame: &[u8]) -> Option<(MacAddress, MacAddress)> {
+        match self.medium {
+            LinkMedium::Ethernet => ethernet_addresses(frame),
+            LinkMedium::Ip => None,
+            LinkMedium::Node => node_addresses(frame),
+        }
+    }
+
+    /// translates a frame from the bus to an ethernet frame for the stack
+    pub fn bus_to_stack(&self, frame: &[u8], out: &mut [u8]) -> Option<usize> {
+        match self.medium {
+            LinkMedium::Ethernet => {
+                if frame.len() > out.len() {
+                    return None;
+                }
+                out[..frame.len()].copy_from_slice(frame);
+                Some(frame.len())
+            }
+            LinkMedium::Ip => {
+                let (&kind, packet) = frame.split_first()?;
+                if kind == TYPE_IPV4_COMPRESSED {
+                    return self.decompress(packet, None, self.address, None, out);
+                }
+                let (ethertype, source) = match kind {
+                    TYPE_IPV4 if packet.len() >= 20 => {
+                        let mut ip = [0; 4];
+                        ip.copy_from_slice(&packet[12..16]);
+                        (ETHERTYPE_IPV4, mac_for_ipv4(ip))
+                    }
+                    TYPE_IPV6 if packet.len() >= 40 => {
+                        (ETHERTYPE_IPV6, mac_for_ipv6(&packet[8..24]))
+                    }
+                    _ => return None,
+                };
+                let len = ETHERNET_HEADER_SIZE + packet.len();
+                if len > out.len() {
+                    return None;
+                }
+                // the ip layer of the stack filters by address, so every packet is addressed to us
+                let destination = self.group_destination(self.address, ethertype, packet);
+                write_ethernet_header(out, destination, source, ethertype);
+                out[ETHERNET_HEADER_SIZE..len].copy_from_slice(packet);
+                Some(len)
+            }
+            LinkMedium::Node => {
+                let (destination, source) = node_addresses(frame)?;
+                if frame[0] == TYPE_IPV4_COMPRESSED {
+                    let link = Some(LinkNodes {
+                        destination: frame[1],
+                        source: frame[2],
+                    });
+                    let compressed = &frame[NODE_HEADER_SIZE..];
+                    return self.decompress(compressed, link, destination, Some(source), out);
+                }
+                let (ethertype, header) = match frame[0] {
+                    TYPE_IPV4 => (ETHERTYPE_IPV4, NODE_HEADER_SIZE),
+                    TYPE_IPV6 => (ETHERTYPE_IPV6, NODE_HEADER_SIZE),
+                    TYPE_ARP => (ETHERTYPE_ARP, NODE_HEADER_SIZE),
+                    TYPE_OTHER if frame.len() >= NODE_HEADER_SIZE + 2 => (
+                        u16::from_be_bytes([frame[3], frame[4]]),
+                        NODE_HEADER_SIZE + 2,
+                    ),
+                    _ => return None,
+                };
+                let payload = &frame[header..];
+                let len = ETHERNET_HEADER_SIZE + payload.len();
+                if len > out.len() {
+                    return None;
+                }
+                let destination = self.group_destination(destination, ethertype, payload);
+                write_ethernet_header(out, destination, source, ethertype);
+                out[ETHERNET_HEADER_SIZE..len].copy_from_slice(payload);
+                Some(len)
+            }
+        }
+    }
+
+    /// answers an arp request of the stack with the mac address derived from the ip
+    fn arp_reply(&self, frame: &[u8]) -> Option<[u8; ARP_FRAME_SIZE]> {
+        if frame.len() < ARP_FRAME_SIZE {
+            return None;
+        }
+        let arp = &frame[ETHERNET_HEADER_SIZE..];
+        let is_ipv4_over_ethernet = arp[0..6] == [0x00, 0x01, 0x08, 0x00, 6, 4];
+        let is_request = arp[6..8] == [0x00, 0x01];
+        if !is_ipv4_over_ethernet || !is_request {
+            return None;
+        }
+        let mut requester_mac = [0; 6];
+        let mut requester_ip = [0; 4];
+        let mut target_ip = [0; 4];
+        requester_mac.copy_from_slice(&arp[8..14]);
+        requester_ip.copy_from_slice(&arp[14..18]);
+        target_ip.copy_from_slice(&arp[24..28]);
+        if target_ip == requester_ip {
+            // probe or announcement of our own address
+            return None;
+        }
+        let target_mac = mac_for_ipv4(target_ip);
+
+        let mut reply = [0; ARP_FRAME_SIZE];
+        write_ethernet_header(&mut reply, requester_mac, target_mac, ETHERTYPE_ARP);
+        let out = &mut reply[ETHERNET_HEADER_SIZE..];
+        out[0..6].copy_from_slice(&[0x00, 0x01, 0x08, 0x00, 6, 4]);
+        out[6..8].copy_from_slice(&[0x00, 0x02]);
+        out[8..14].copy_from_slice(&target_mac);
+        out[14..18].copy_from_slice(&target_ip);
+        out[18..24].copy_from_slice(&requester_mac);
+        out[24..28].copy_from_slice(&requester_ip);
+        Some(reply)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address_filter::AddressFilter;
+
+    #[test]
+    fn link_local_solicitation_passes_the_filter() {
+        let mac = node_mac(3);
+        let link_local = link_local_ipv6(mac);
+        assert_eq!(link_local[..8], [0xFE, 0x80, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(mac_for_ipv6(&link_local), mac);
+        // neighbor solicitation for the link local address, sent to ff02::1:ffXX:XXXX
+        let mut packet = [0; 40];
+        packet[0] = 0x60;
+        packet[24..26].copy_from_slice(&[0xFF, 0x02]);
+        packet[35] = 0x01;
+        packet[36] = 0xFF;
+        packet[37..40].copy_from_slice(&link_local[13..16]);
+        let group = multicast_mac(ETHERTYPE_IPV6, &packet).unwrap();
+        assert_eq!(group, solicited_node_mac(link_local));
+        assert!(AddressFilter::default().accepts(mac, group));
+        assert!(!AddressFilter::default().accepts(node_mac(4), group));
+    }
+}
@@ -0,0 +1,21 @@
+use defmt::Format;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+
+pub const OUTCOME_CHANNEL_SIZE: usize = 8;
+
+/// final status of a frame the stack handed to the driver. `collisions` counts
+/// the attempts that were lost to a collision before the frame was finished
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum TransmitOutcome {
+    /// the frame went out on the bus
+    Delivered { collisions: usize },
+    /// the frame was dropped after the backoff policy ran out of attempts
+    Abandoned { collisions: usize },
+    /// the frame was dropped and the last attempt failed with a framing error
+    FramingError { collisions: usize },
+}
+
+/// outcomes are published with `try_send`, if nobody drains the channel new
+/// outcomes are dropped instead of stalling the bus
+pub type OutcomeChannel = Channel<CriticalSectionRawMutex, TransmitOutcome, OUTCOME_CHANNEL_SIZE>;
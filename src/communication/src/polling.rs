@@ -0,0 +1,254 @@
+//! master/slave bus access: the master polls its slaves in turn and a slave only transmits
+//! after it was polled. It sends up to a turn of its queued frames and ends its response
+//! with an end frame, the master sends its own frames between two polls.
+//!
+//! Control frames are [`crate::control`] frames with mode `0x50`. A slave that does not end
+//! its response within its timeout missed the poll, after enough missed polls in a row it
+//! is reported as unresponsive on the report channel and as responsive once it answers again
+use crate::control::{self, CONTROL_FRAME_SIZE, POLLING_MODE};
+
+use defmt::*;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_time::{Duration, Instant};
+
+use core::cell::Cell;
+
+const POLL: u8 = 0x01;
+const END: u8 = 0x02;
+/// missed polls are counted for this many slaves
+pub const MAX_SLAVES: usize = 32;
+pub const SLAVE_REPORT_CHANNEL_SIZE: usize = 4;
+
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum SlaveReport {
+    /// the slave missed this many polls in a row
+    Unresponsive { node: u8, missed_polls: usize },
+    /// an unresponsive slave answered a poll again
+    Responsive { node: u8 },
+}
+
+/// reports are published with `try_send`, if nobody drains the channel new reports are dropped
+pub type SlaveReportChannel =
+    Channel<CriticalSectionRawMutex, SlaveReport, SLAVE_REPORT_CHANNEL_SIZE>;
+
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum PollRole {
+    Master,
+    Slave,
+}
+
+#[derive(Clone, Copy)]
+pub struct PolledSlave {
+    pub node: u8,
+    /// from the end of the poll until the end frame of the slave
+    pub response_timeout: Duration,
+}
+
+#[derive(Clone, Copy)]
+pub struct PollingConfig {
+    pub role: PollRole,
+    /// the slaves the master polls in this order, unused by slaves
+    pub slaves: &'static [PolledSlave],
+    /// frames a slave sends per poll and the master sends between two polls
+    pub frames_per_turn: usize,
+    /// missed polls in a row after which a slave is reported as unresponsive
+    pub unresponsive_after: usize,
+    pub reports: Option<&'static SlaveReportChannel>,
+}
+
+impl Default for PollingConfig {
+    fn default() -> Self {
+        Self {
+            role: PollRole::Slave,
+            slaves: &[],
+            frames_per_turn: 2,
+            unresponsive_after: 3,
+            reports: None,
+        }
+    }
+}
+
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PollFrame {
+    Poll { destination: u8, source: u8 },
+    End { destination: u8, source: u8 },
+}
+
+impl PollFrame {
+    pub fn parse(burst: &[u8]) -> Option<Self> {
+        let (kind, destination, source) = control::parse(POLLING_MODE, burst)?;
+        match kind {
+            POLL => Some(Self::Poll {
+                destination,
+                source,
+            }),
+            END => Some(Self::End {
+                destination,
+                source,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn encode(&self) -> [u8; CONTROL_FRAME_SIZE] {
+        let (kind, destination, source) = match *self {
+            Self::Poll {
+                destination,
+                source,
+            } => (POLL, destination, source),
+            Self::End {
+                destination,
+                source,
+            } => (END, destination, source),
+        };
+        control::encode(POLLING_MODE, kind, destination, source)
+    }
+}
+
+/// polling progress, shared between the transmit and receive halves of the driver
+pub(crate) struct PollState {
+    config: PollingConfig,
+    id: u8,
+    /// master: the slave whose response is awaited and until when
+    awaiting: Cell<Option<(usize, Instant)>>,
+    /// master: the slave polled next
+    next_slave: Cell<usize>,
+    missed_polls: [Cell<usize>; MAX_SLAVES],
+    /// slave: the master that polled this node, until the response is done
+    polled_by: Cell<Option<u8>>,
+    /// frames sent in the current turn
+    turn_frames: Cell<usize>,
+}
+
+impl PollState {
+    pub fn new(config: PollingConfig, id: u8) -> Self {
+        if config.role == PollRole::Master {
+            assert!(
+                !config.slaves.is_empty() && config.slaves.len() <= MAX_SLAVES,
+                "the master needs between one and MAX_SLAVES slaves"
+            );
+        }
+        Self {
+            config,
+            id,
+            awaiting: Cell::new(None),
+            next_slave: Cell::new(0),
+            missed_polls: Default::default(),
+            polled_by: Cell::new(None),
+            turn_frames: Cell::new(0),
+        }
+    }
+
+    pub fn is_master(&self) -> bool {
+        self.config.role == PollRole::Master
+    }
+
+    /// another frame fits the current turn
+    pub fn has_budget(&self) -> bool {
+        self.turn_frames.get() < self.config.frames_per_turn
+    }
+
+    pub fn on_frame_sent(&self) {
+        self.turn_frames.set(self.turn_frames.get() + 1);
+    }
+
+    /// master: the slave being waited for and until when
+    pub fn awaiting(&self) -> Option<(u8, Instant)> {
+        let (index, deadline) = self.awaiting.get()?;
+        Some((self.config.slaves[index].node, deadline))
+    }
+
+    /// master: the poll frame for the next slave
+    pub fn next_poll(&self) -> PollFrame {
+        PollFrame::Poll {
+            destination: self.config.slaves[self.next_slave.get()].node,
+            source: self.id,
+        }
+    }
+
+    /// master: the poll went out, the slave is waited for
+    pub fn on_polled(&self) {
+        let index = self.next_slave.get();
+        let deadline = Instant::now() + self.config.slaves[index].response_timeout;
+        self.awaiting.set(Some((index, deadline)));
+        self.next_slave.set((index + 1) % self.config.slaves.len());
+        self.turn_frames.set(0);
+    }
+
+    /// master: the awaited slave did not end its response in time
+    pub fn on_response_timeout(&self) {
+        let Some((index, _)) = self.awaiting.take() else {
+            return;
+        };
+        let node = self.config.slaves[index].node;
+        let missed_polls = self.missed_polls[index].get() + 1;
+        self.missed_polls[index].set(missed_polls);
+        info!("slave {} missed a poll", node);
+        if missed_polls == self.config.unresponsive_after {
+            self.report(SlaveReport::Unresponsive { node, missed_polls });
+        }
+    }
+
+    fn on_response(&self, source: u8) {
+        let Some((index, _)) = self.awaiting.get() else {
+            return;
+        };
+        if self.config.slaves[index].node != source {
+            return;
+        }
+        self.awaiting.set(None);
+        if self.missed_polls[index].replace(0) >= self.config.unresponsive_after {
+            self.report(SlaveReport::Responsive { node: source });
+        }
+    }
+
+    fn report(&self, report: SlaveReport) {
+        let Some(reports) = self.config.reports else {
+            return;
+        };
+        if reports.try_send(report).is_err() {
+            info!("slave report channel full, dropping {:?}", report);
+        }
+    }
+
+    /// slave: the master the response goes to, `None` outside of the response window
+    pub fn polled_by(&self) -> Option<u8> {
+        self.polled_by.get()
+    }
+
+    /// slave: the end frame closing the response
+    pub fn end_frame(&self, master: u8) -> PollFrame {
+        PollFrame::End {
+            destination: master,
+            source: self.id,
+        }
+    }
+
+    /// slave: the response window is over
+    pub fn on_response_sent(&self) {
+        self.polled_by.set(None);
+    }
+
+    pub fn on_frame(&self, frame: PollFrame) {
+        match frame {
+            PollFrame::Poll {
+                destination,
+                source,
+            } => {
+                if !self.is_master() && destination == self.id {
+                    self.polled_by.set(Some(source));
+                    self.turn_frames.set(0);
+                }
+            }
+            PollFrame::End {
+                destination,
+                source,
+            } => {
+                if self.is_master() && destination == self.id {
+                    self.on_response(source);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! transmit priority classes: the driver takes every frame off the single transmit channel of
+//! the stack as soon as it arrives and sorts it into the queue of its class. The most urgent
+//! class with a frame goes first, unless a less urgent class was passed over
+//! `starvation_limit` times in a row, then that one gets a turn.
+//!
+//! A frame whose class queue is full stays in the channel until its class has room again,
+//! so the stack sees the backpressure and no frame is lost
+use crate::compression::PROTOCOL_UDP;
+use crate::medium::{ethertype, ETHERNET_HEADER_SIZE, ETHERTYPE_IPV4, ETHERTYPE_IPV6};
+
+/// most classes a config may use
+pub const MAX_CLASSES: usize = 4;
+/// most frames queued per class
+pub const MAX_DEPTH: usize = 8;
+
+const IPV6_HEADER_SIZE: usize = 40;
+
+pub struct PriorityConfig {
+    /// class of a frame from the stack, 0 is the most urgent. Classes past the last one
+    /// end up in the last one
+    pub classify: fn(&[u8]) -> usize,
+    /// number of classes, at most [`MAX_CLASSES`]
+    pub classes: usize,
+    /// a waiting class gets a turn after frames of more urgent classes went ahead of it
+    /// this often, at least 1
+    pub starvation_limit: usize,
+    /// room for the queued frames, every class gets the same number of frames of the mtu,
+    /// at most [`MAX_DEPTH`]
+    pub storage: &'static mut [u8],
+}
+
+/// differentiated services code point of an ip frame from the stack
+pub fn dscp(frame: &[u8]) -> Option<u8> {
+    let ip = frame.get(ETHERNET_HEADER_SIZE..)?;
+    match ethertype(frame) {
+        ETHERTYPE_IPV4 => Some(ip.get(1)? >> 2),
+        ETHERTYPE_IPV6 => {
+            let traffic_class = ip.first()? << 4 | ip.get(1)? >> 4;
+            Some(traffic_class >> 2)
+        }
+        _ => None,
+    }
+}
+
+/// destination port of a udp frame from the stack
+pub fn udp_destination_port(frame: &[u8]) -> Option<u16> {
+    let ip = frame.get(ETHERNET_HEADER_SIZE..)?;
+    let udp = match ethertype(frame) {
+        ETHERTYPE_IPV4 if *ip.get(9)? == PROTOCOL_UDP => {
+            let header_len = (ip[0] & 0x0F) as usize * 4;
+            ip.get(header_len..)?
+        }
+        ETHERTYPE_IPV6 if *ip.get(6)? == PROTOCOL_UDP => ip.get(IPV6_HEADER_SIZE..)?,
+        _ => return None,
+    };
+    Some(u16::from_be_bytes([*udp.get(2)?, *udp.get(3)?]))
+}
+
+/// three classes by dscp: expedited forwarding and the network control code points first,
+/// low effort (CS1) last and everything else in between
+pub fn classify_by_dscp(frame: &[u8]) -> usize {
+    match dscp(frame) {
+        Some(dscp) if dscp >= 40 => 0,
+        Some(dscp) if dscp >> 3 == 1 => 2,
+        _ => 1,
+    }
+}
+
+pub(crate) struct PriorityQueues {
+    classify: fn(&[u8]) -> usize,
+    classes: usize,
+    starvation_limit: usize,
+    depth: usize,
+    mtu: usize,
+    storage: &'static mut [u8],
+    lens: [[usize; MAX_DEPTH]; MAX_CLASSES],
+    head: [usize; MAX_CLASSES],
+    count: [usize; MAX_CLASSES],
+    /// turns a waiting class was passed over
+    skipped: [usize; MAX_CLASSES],
+    /// class of the frame being transmitted
+    current: Option<usize>,
+}
+
+impl PriorityQueues {
+    pub fn new(config: PriorityConfig, mtu: usize) -> Self {
+        assert!(
+            config.classes > 0 && config.classes <= MAX_CLASSES,
+            "between one and MAX_CLASSES classes"
+        );
+        assert!(
+            config.starvation_limit > 0,
+            "the starvation limit has to be at least 1"
+        );
+        let depth = (config.storage.len() / (config.classes * mtu)).min(MAX_DEPTH);
+        assert!(depth > 0, "the storage has to hold a frame per class");
+        Self {
+            classify: config.classify,
+            classes: config.classes,
+            starvation_limit: config.starvation_limit,
+            depth,
+            mtu,
+            storage: config.storage,
+            lens: [[0; MAX_DEPTH]; MAX_CLASSES],
+            head: [0; MAX_CLASSES],
+            count: [0; MAX_CLASSES],
+            skipped: [0; MAX_CLASSES],
+            current: None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count.iter().all(|&count| count == 0)
+    }
+
+    fn slot(&mut self, class: usize, index: usize) -> &mut [u8] {
+        let start = (class * self.depth + index) * self.mtu;
+        &mut self.storage[start..start + self.mtu]
+    }
+
+    /// queues a frame of at most the mtu from the stack, `Err` if its class is full
+    pub fn push(&mut self, frame: &[u8]) -> Result<(), ()> {
+        let class = (self.classify)(frame).min(self.classes - 1);
+        if self.count[class] == self.depth {
+            return Err(());
+        }
+        let index = (self.head[class] + self.count[class]) % self.depth;
+        self.slot(class, index)[..frame.len()].copy_from_slice(frame);
+        self.lens[class][index] = frame.len();
+        self.count[class] += 1;
+        Ok(())
+    }
+
+    fn select(&mut self) -> Option<usize> {
+        let first = (0..self.classes).find(|&class| self.count[class] > 0)?;
+        let starving = (first + 1..self.classes)
+            .find(|&class| self.count[class] > 0 && self.skipped[class] >= self.starvation_limit);
+        let selected = starving.unwrap_or(first);
+        for class in selected + 1..self.classes {
+            if self.count[class] > 0 {
+                self.skipped[class] += 1;
+            }
+        }
+        self.skipped[selected] = 0;
+        Some(selected)
+    }
+
+    /// the frame to transmit, it stays the same until [`Self::pop_current`]
+    pub fn current(&mut self) -> Option<&mut [u8]> {
+        if self.current.is_none() {
+            self.current = self.select();
+        }
+        let class = self.current?;
+        let index = self.head[class];
+        let len = self.lens[class][index];
+        Some(&mut self.slot(class, index)[..len])
+    }
+
+    /// drops the frame to transmit, the one [`Self::current`] would return if it was not
+    /// asked for yet
+    pub fn pop_current(&mut self) {
+        let Some(class) = self.current.take().or_else(|| self.select()) else {
+            return;
+        };
+        self.head[class] = (self.head[class] + 1) % self.depth;
+        self.count[class] -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MTU: usize = 4;
+
+    /// the class is the first byte of the frame
+    fn classify(frame: &[u8]) -> usize {
+        frame[0] as usize
+    }
+
+    /// room for two frames per class, every use is a storage of its own
+    macro_rules! storage {
+        () => {{
+            static mut STORAGE: [u8; 3 * 2 * MTU] = [0; 3 * 2 * MTU];
+            unsafe { &mut *core::ptr::addr_of_mut!(STORAGE) }
+        }};
+    }
+
+    fn queues(storage: &'static mut [u8], starvation_limit: usize) -> PriorityQueues {
+        let config = PriorityConfig {
+            classify,
+            classes: 3,
+            starvation_limit,
+            storage,
+        };
+        PriorityQueues::new(config, MTU)
+    }
+
+    /// classes of the frames in transmit order
+    fn drain(queues: &mut PriorityQueues) -> ([u8; 8], usize) {
+        let mut order = [0; 8];
+        let mut len = 0;
+        while let Some(frame) = queues.current() {
+            order[len] = frame[0];
+            len += 1;
+            queues.pop_current();
+        }
+        (order, len)
+    }
+
+    #[test]
+    fn most_urgent_class_goes_first() {
+        let mut queues = queues(storage!(), 8);
+        for frame in [[2, 0], [1, 0], [0, 0], [5, 1]] {
+            queues.push(&frame).unwrap();
+        }
+        // classes past the last one end up in the last one
+        let (order, len) = drain(&mut queues);
+        assert_eq!(order[..len], [0, 1, 2, 5]);
+        assert!(queues.is_empty());
+    }
+
+    #[test]
+    fn full_class_pushes_back() {
+        let mut queues = queues(storage!(), 8);
+        queues.push(&[1]).unwrap();
+        queues.push(&[1]).unwrap();
+        assert!(queues.push(&[1]).is_err());
+        assert!(queues.push(&[0]).is_ok());
+    }
+
+    #[test]
+    fn waiting_class_gets_a_turn() {
+        let mut queues = queues(storage!(), 2);
+        queues.push(&[2]).unwrap();
+        let mut order = [0; 6];
+        for turn in order.iter_mut() {
+            queues.push(&[0]).unwrap();
+            let frame = queues.current().unwrap();
+            *turn = frame[0];
+            queues.pop_current();
+        }
+        assert_eq!(order, [0, 0, 2, 0, 0, 0]);
+    }
+
+    #[test]
+    fn pop_without_current_drops_the_head() {
+        let mut queues = queues(storage!(), 8);
+        queues.push(&[1, 7]).unwrap();
+        queues.push(&[0, 8]).unwrap();
+        queues.pop_current();
+        assert_eq!(queues.current().unwrap(), [1, 7]);
+        queues.pop_current();
+        queues.pop_current();
+        assert!(queues.is_empty());
+    }
+}
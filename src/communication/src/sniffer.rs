@@ -0,0 +1,31 @@
+//! passive bus monitoring: every burst read from the bus is copied to a channel as it
+//! was on the wire, before reassembly, ARQ or any translation for the stack
+use crate::half_duplex::BUS_FRAME_SIZE;
+use crate::ReadError;
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_time::Instant;
+
+pub const SNIFFER_CHANNEL_SIZE: usize = 4;
+
+pub struct SniffedFrame {
+    /// when the read completed
+    pub timestamp: Instant,
+    /// length of the frame in `data` or why the read failed
+    pub result: Result<usize, ReadError>,
+    pub data: [u8; BUS_FRAME_SIZE],
+}
+
+impl SniffedFrame {
+    /// the bytes that were read, empty for a failed read
+    pub fn frame(&self) -> &[u8] {
+        match self.result {
+            Ok(len) => &self.data[..len],
+            Err(_) => &[],
+        }
+    }
+}
+
+/// frames are published with `try_send`, if nobody drains the channel new frames are dropped
+pub type SnifferChannel = Channel<CriticalSectionRawMutex, SniffedFrame, SNIFFER_CHANNEL_SIZE>;
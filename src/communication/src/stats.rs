@@ -0,0 +1,263 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use defmt::Format;
+
+/// snapshot of the link counters
+#[derive(Debug, Format, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LinkStats {
+    pub frames_sent: u32,
+    pub bytes_sent: u32,
+    pub collisions: u32,
+    pub framing_errors: u32,
+    pub overflow_errors: u32,
+    pub checksum_errors: u32,
+    pub backoffs: u32,
+    pub abandoned_frames: u32,
+    pub frames_received: u32,
+    pub rx_frames_lost: u32,
+    pub ack_timeouts: u32,
+    pub duplicate_frames: u32,
+    pub reassembly_failures: u32,
+    pub filtered_frames: u32,
+    pub echoes_suppressed: u32,
+    pub jams_sent: u32,
+    pub jams_received: u32,
+    pub beacons_sent: u32,
+    pub beacons_received: u32,
+    pub tokens_regenerated: u32,
+    pub missed_polls: u32,
+    pub arbitrations_lost: u32,
+}
+
+/// counters updated by the driver while it runs. Place them in a static and
+/// hand them to the driver config, then query them through a [`LinkStatsHandle`]
+pub struct LinkCounters {
+    frames_sent: AtomicU32,
+    bytes_sent: AtomicU32,
+    collisions: AtomicU32,
+    framing_errors: AtomicU32,
+    overflow_errors: AtomicU32,
+    checksum_errors: AtomicU32,
+    backoffs: AtomicU32,
+    abandoned_frames: AtomicU32,
+    frames_received: AtomicU32,
+    rx_frames_lost: AtomicU32,
+    ack_timeouts: AtomicU32,
+    duplicate_frames: AtomicU32,
+    reassembly_failures: AtomicU32,
+    filtered_frames: AtomicU32,
+    echoes_suppressed: AtomicU32,
+    jams_sent: AtomicU32,
+    jams_received: AtomicU32,
+    beacons_sent: AtomicU32,
+    beacons_received: AtomicU32,
+    tokens_regenerated: AtomicU32,
+    missed_polls: AtomicU32,
+    arbitrations_lost: AtomicU32,
+}
+
+impl Default for LinkCounters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LinkCounters {
+    pub const fn new() -> Self {
+        Self {
+            frames_sent: AtomicU32::new(0),
+            bytes_sent: AtomicU32::new(0),
+            collisions: AtomicU32::new(0),
+            framing_errors: AtomicU32::new(0),
+            overflow_errors: AtomicU32::new(0),
+            checksum_errors: AtomicU32::new(0),
+            backoffs: AtomicU32::new(0),
+            abandoned_frames: AtomicU32::new(0),
+            frames_received: AtomicU32::new(0),
+            rx_frames_lost: AtomicU32::new(0),
+            ack_timeouts: AtomicU32::new(0),
+            duplicate_frames: AtomicU32::new(0),
+            reassembly_failures: AtomicU32::new(0),
+            filtered_frames: AtomicU32::new(0),
+            echoes_suppressed: AtomicU32::new(0),
+            jams_sent: AtomicU32::new(0),
+            jams_received: AtomicU32::new(0),
+            beacons_sent: AtomicU32::new(0),
+            beacons_received: AtomicU32::new(0),
+            tokens_regenerated: AtomicU32::new(0),
+            missed_polls: AtomicU32::new(0),
+            arbitrations_lost: AtomicU32::new(0),
+        }
+    }
+
+    pub fn snapshot(&self) -> LinkStats {
+        LinkStats {
+            frames_sent: self.frames_sent.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            collisions: self.collisions.load(Ordering::Relaxed),
+            framing_errors: self.framing_errors.load(Ordering::Relaxed),
+            overflow_errors: self.overflow_errors.load(Ordering::Relaxed),
+            checksum_errors: self.checksum_errors.load(Ordering::Relaxed),
+            backoffs: self.backoffs.load(Ordering::Relaxed),
+            abandoned_frames: self.abandoned_frames.load(Ordering::Relaxed),
+            frames_received: self.frames_received.load(Ordering::Relaxed),
+            rx_frames_lost: self.rx_frames_lost.load(Ordering::Relaxed),
+            ack_timeouts: self.ack_timeouts.load(Ordering::Relaxed),
+            duplicate_frames: self.duplicate_frames.load(Ordering::Relaxed),
+            reassembly_failures: self.reassembly_failures.load(Ordering::Relaxed),
+            filtered_frames: self.filtered_frames.load(Ordering::Relaxed),
+            echoes_suppressed: self.echoes_suppressed.load(Ordering::Relaxed),
+            jams_sent: self.jams_sent.load(Ordering::Relaxed),
+            jams_received: self.jams_received.load(Ordering::Relaxed),
+            beacons_sent: self.beacons_sent.load(Ordering::Relaxed),
+            beacons_received: self.beacons_received.load(Ordering::Relaxed),
+            tokens_regenerated: self.tokens_regenerated.load(Ordering::Relaxed),
+            missed_polls: self.missed_polls.load(Ordering::Relaxed),
+            arbitrations_lost: self.arbitrations_lost.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn reset(&self) {
+        self.frames_sent.store(0, Ordering::Relaxed);
+        self.bytes_sent.store(0, Ordering::Relaxed);
+        self.collisions.store(0, Ordering::Relaxed);
+        self.framing_errors.store(0, Ordering::Relaxed);
+        self.overflow_errors.store(0, Ordering::Relaxed);
+        self.checksum_errors.store(0, Ordering::Relaxed);
+        self.backoffs.store(0, Ordering::Relaxed);
+        self.abandoned_frames.store(0, Ordering::Relaxed);
+        self.frames_received.store(0, Ordering::Relaxed);
+        self.rx_frames_lost.store(0, Ordering::Relaxed);
+        self.ack_timeouts.store(0, Ordering::Relaxed);
+        self.duplicate_frames.store(0, Ordering::Relaxed);
+        self.reassembly_failures.store(0, Ordering::Relaxed);
+        self.filtered_frames.store(0, Ordering::Relaxed);
+        self.echoes_suppressed.store(0, Ordering::Relaxed);
+        self.jams_sent.store(0, Ordering::Relaxed);
+        self.jams_received.store(0, Ordering::Relaxed);
+        self.beacons_sent.store(0, Ordering::Relaxed);
+        self.beacons_received.store(0, Ordering::Relaxed);
+        self.tokens_regenerated.store(0, Ordering::Relaxed);
+        self.missed_polls.store(0, Ordering::Relaxed);
+        self.arbitrations_lost.store(0, Ordering::Relaxed);
+    }
+}
+
+/// cloneable read access to the counters of a running driver
+#[derive(Clone, Copy)]
+pub struct LinkStatsHandle(&'static LinkCounters);
+
+impl LinkStatsHandle {
+    pub fn new(counters: &'static LinkCounters) -> Self {
+        Self(counters)
+    }
+
+    pub fn snapshot(&self) -> LinkStats {
+        self.0.snapshot()
+    }
+
+    pub fn reset(&self) {
+        self.0.reset()
+    }
+}
+
+/// used by the driver to update the counters, does nothing when no counters were configured
+#[derive(Clone, Copy, Default)]
+pub(crate) struct StatsRecorder(Option<&'static LinkCounters>);
+
+impl StatsRecorder {
+    pub fn new(counters: Option<&'static LinkCounters>) -> Self {
+        Self(counters)
+    }
+
+    fn add(&self, counter: impl FnOnce(&LinkCounters) -> &AtomicU32, amount: u32) {
+        if let Some(counters) = self.0 {
+            counter(counters).fetch_add(amount, Ordering::Relaxed);
+        }
+    }
+
+    pub fn frame_sent(&self, bytes: usize) {
+        self.add(|c| &c.frames_sent, 1);
+        self.add(|c| &c.bytes_sent, bytes as u32);
+    }
+
+    pub fn collision(&self) {
+        self.add(|c| &c.collisions, 1);
+    }
+
+    pub fn framing_error(&self) {
+        self.add(|c| &c.framing_errors, 1);
+    }
+
+    pub fn overflow_error(&self) {
+        self.add(|c| &c.overflow_errors, 1);
+    }
+
+    pub fn checksum_error(&self) {
+        self.add(|c| &c.checksum_errors, 1);
+    }
+
+    pub fn backoff(&self) {
+        self.add(|c| &c.backoffs, 1);
+    }
+
+    pub fn abandoned(&self) {
+        self.add(|c| &c.abandoned_frames, 1);
+    }
+
+    pub fn frame_received(&self) {
+        self.add(|c| &c.frames_received, 1);
+    }
+
+    pub fn rx_lost(&self) {
+        self.add(|c| &c.rx_frames_lost, 1);
+    }
+
+    pub fn ack_timeout(&self) {
+        self.add(|c| &c.ack_timeouts, 1);
+    }
+
+    pub fn duplicate_frame(&self) {
+        self.add(|c| &c.duplicate_frames, 1);
+    }
+
+    pub fn reassembly_failure(&self) {
+        self.add(|c| &c.reassembly_failures, 1);
+    }
+
+    pub fn frame_filtered(&self) {
+        self.add(|c| &c.filtered_frames, 1);
+    }
+
+    pub fn echo_suppressed(&self) {
+        self.add(|c| &c.echoes_suppressed, 1);
+    }
+
+    pub fn jam_sent(&self) {
+        self.add(|c| &c.jams_sent, 1);
+    }
+
+    pub fn jam_received(&self) {
+        self.add(|c| &c.jams_received, 1);
+    }
+
+    pub fn beacon_sent(&self) {
+        self.add(|c| &c.beacons_sent, 1);
+    }
+
+    pub fn beacon_received(&self) {
+        self.add(|c| &c.beacons_received, 1);
+    }
+
+    pub fn token_regenerated(&self) {
+        self.add(|c| &c.tokens_regenerated, 1);
+    }
+
+    pub fn missed_poll(&self) {
+        self.add(|c| &c.missed_polls, 1);
+    }
+
+    pub fn arbitration_lost(&self) {
+        self.add(|c| &c.arbitrations_lost, 1);
+    }
+}
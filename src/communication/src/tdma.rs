@@ -0,0 +1,151 @@
+//! slot scheduled bus access: time is split into superframes of equally long slots and a
+//! node only transmits in the slots it was given, so frames never collide and nobody backs off.
+//!
+//! With a beacon time base one node starts every superframe with a [`BEACON`] in slot 0,
+//! everyone else aligns the superframe to the moment the beacon was read. With a shared
+//! time base the superframes start at multiples of the superframe length since tick 0
+use defmt::*;
+use embassy_time::{Duration, Instant};
+
+use core::cell::Cell;
+
+/// burst that starts a superframe, a frame of exactly this content is never handed to the stack
+pub const BEACON: [u8; 6] = [0x7E, 0xBE, 0xAC, 0x07, 0x7E, 0xBE];
+
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum TimeBase {
+    /// this node sends the beacon in slot 0 of every superframe
+    SendBeacon,
+    /// align to the beacon of another node, nothing is sent before the first beacon
+    FollowBeacon,
+    /// the clocks of all nodes already agree, e.g. they were started by the same pulse
+    Shared,
+}
+
+#[derive(Clone, Copy)]
+pub struct TdmaConfig {
+    /// length of every slot, it has to hold the longest burst plus the guard
+    pub slot: Duration,
+    /// time a byte takes on the line, start and stop bits included. Bytes that adapters
+    /// like cobs or fcs add to a burst are not counted, leave room for them in the guard
+    pub byte_time: Duration,
+    /// slots in one superframe
+    pub slots: u8,
+    /// slots this node transmits in, slot 0 is taken by the beacon with a beacon time base
+    pub own_slots: &'static [u8],
+    /// no burst starts in this last part of a slot, covers the skew between the nodes
+    pub guard: Duration,
+    pub time_base: TimeBase,
+}
+
+impl Default for TdmaConfig {
+    fn default() -> Self {
+        Self {
+            // a full bus frame at 500 kbaud takes about 21 ms
+            slot: Duration::from_millis(25),
+            byte_time: Duration::from_micros(20),
+            slots: 4,
+            own_slots: &[],
+            guard: Duration::from_millis(1),
+            time_base: TimeBase::FollowBeacon,
+        }
+    }
+}
+
+/// superframe alignment, shared between the transmit and receive halves of the driver
+pub(crate) struct TdmaSchedule {
+    config: TdmaConfig,
+    superframe_start: Cell<Option<Instant>>,
+}
+
+impl TdmaSchedule {
+    /// `max_burst` is the longest burst the driver puts on the bus
+    pub fn new(config: TdmaConfig, max_burst: usize) -> Self {
+        assert!(config.slots > 0, "a superframe needs slots");
+        assert!(
+            config.guard + config.byte_time * max_burst as u32 <= config.slot,
+            "the slot has to hold the longest burst plus the guard"
+        );
+        assert!(
+            !config.own_slots.is_empty() && config.own_slots.iter().all(|&s| s < config.slots),
+            "own slots have to be in the superframe"
+        );
+        let start = match config.time_base {
+            TimeBase::Shared => Some(Instant::from_ticks(0)),
+            _ => {
+                assert!(
+                    !config.own_slots.contains(&0),
+                    "slot 0 is taken by the beacon"
+                );
+                None
+            }
+        };
+        Self {
+            config,
+            superframe_start: Cell::new(start),
+        }
+    }
+
+    pub fn sends_beacon(&self) -> bool {
+        self.config.time_base == TimeBase::SendBeacon
+    }
+
+    /// how long to wait before looking again while there is no alignment yet
+    pub fn slot(&self) -> Duration {
+        self.config.slot
+    }
+
+    fn superframe(&self) -> Duration {
+        self.config.slot * self.config.slots as u32
+    }
+
+    /// when the beacon node has to send its next beacon, right away if it never sent one
+    pub fn next_beacon(&self) -> Instant {
+        match self.superframe_start.get() {
+            Some(start) => start + self.superframe(),
+            None => Instant::now(),
+        }
+    }
+
+    pub fn on_beacon_sent(&self) {
+        self.superframe_start.set(Some(Instant::now()));
+    }
+
+    /// aligns a following node to a beacon read just now
+    pub fn on_beacon_received(&self) {
+        if self.config.time_base == TimeBase::FollowBeacon {
+            self.superframe_start.set(Some(Instant::now()));
+        }
+    }
+
+    /// the earliest time from `now` at which a burst of `len` bytes may start and still end
+    /// before the guard, `None` while the superframes are not aligned
+    pub fn next_slot(&self, now: Instant, len: usize) -> Option<Instant> {
+        let start = self.superframe_start.get()?;
+        let slot = self.config.slot.as_ticks();
+        let superframe = self.superframe().as_ticks();
+        let offset = now.as_ticks().saturating_sub(start.as_ticks()) % superframe;
+        let current = offset / slot;
+        let within = offset % slot;
+        let slots = self.config.slots as u64;
+        for i in 0..=slots {
+            let index = ((current + i) % slots) as u8;
+            if !self.config.own_slots.contains(&index) {
+                continue;
+            }
+            if i == 0 {
+                let airtime = self.config.byte_time * len as u32;
+                if within + (self.config.guard + airtime).as_ticks() <= slot {
+                    return Some(now);
+                }
+                continue;
+            }
+            return Some(now + Duration::from_ticks(i * slot - within));
+        }
+        None
+    }
+}
+
+pub(crate) fn is_beacon(burst: &[u8]) -> bool {
+    burst == BEACON
+}
@@ -0,0 +1,319 @@
+//! token passing bus access: a token frame circulates along the ring of node ids in
+//! ascending order and only the node holding it transmits, up to a budget of frames per hold.
+//!
+//! Control frames are [`crate::control`] frames with mode `0x70`. A node joins by announcing
+//! itself with a join frame, which is the only frame sent without the token, and leaves with
+//! a leave frame while it holds the token. A node that does not use the token it was passed
+//! is dropped from the ring, and a lost token is regenerated by the lowest node once the bus
+//! was silent for the token timeout
+use crate::control::{self, CONTROL_FRAME_SIZE, TOKEN_MODE};
+
+use defmt::*;
+use embassy_time::{Duration, Instant};
+
+use core::cell::Cell;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+const TOKEN: u8 = 0x01;
+const JOIN: u8 = 0x02;
+const LEAVE: u8 = 0x03;
+
+/// join and leave the ring while the driver runs
+pub struct RingMembership {
+    member: AtomicBool,
+}
+
+impl Default for RingMembership {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RingMembership {
+    /// starts out wanting to be in the ring
+    pub const fn new() -> Self {
+        Self {
+            member: AtomicBool::new(true),
+        }
+    }
+
+    pub fn join(&self) {
+        self.member.store(true, Ordering::Relaxed);
+    }
+
+    /// the node leaves the next time it gets the token
+    pub fn leave(&self) {
+        self.member.store(false, Ordering::Relaxed);
+    }
+
+    pub fn wants_membership(&self) -> bool {
+        self.member.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct TokenConfig {
+    /// node ids in the ring from the start, other nodes are learned from their control frames
+    pub ring: &'static [u8],
+    /// frames sent per hold of the token
+    pub hold_budget: usize,
+    /// the token is regenerated once the bus was silent this long, node `n` waits `n` more
+    /// regeneration steps so that the lowest node regenerates first
+    pub token_timeout: Duration,
+    pub regeneration_step: Duration,
+    /// the successor has this long to use the token before it is dropped from the ring
+    pub pass_timeout: Duration,
+    /// join again if the token did not come by for this long, longer than a round of the ring
+    pub rejoin_after: Duration,
+    /// without it the node stays in the ring
+    pub membership: Option<&'static RingMembership>,
+}
+
+impl Default for TokenConfig {
+    fn default() -> Self {
+        Self {
+            ring: &[],
+            hold_budget: 4,
+            token_timeout: Duration::from_millis(50),
+            regeneration_step: Duration::from_millis(2),
+            pass_timeout: Duration::from_millis(10),
+            rejoin_after: Duration::from_millis(500),
+            membership: None,
+        }
+    }
+}
+
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ControlFrame {
+    Token { destination: u8, source: u8 },
+    Join(u8),
+    Leave(u8),
+}
+
+impl ControlFrame {
+    pub fn parse(burst: &[u8]) -> Option<Self> {
+        let (kind, destination, source) = control::parse(TOKEN_MODE, burst)?;
+        match kind {
+            TOKEN => Some(Self::Token {
+                destination,
+                source,
+            }),
+            JOIN => Some(Self::Join(source)),
+            LEAVE => Some(Self::Leave(source)),
+            _ => None,
+        }
+    }
+
+    pub fn encode(&self) -> [u8; CONTROL_FRAME_SIZE] {
+        let (kind, destination, source) = match *self {
+            Self::Token {
+                destination,
+                source,
+            } => (TOKEN, destination, source),
+            Self::Join(source) => (JOIN, 0, source),
+            Self::Leave(source) => (LEAVE, 0, source),
+        };
+        control::encode(TOKEN_MODE, kind, destination, source)
+    }
+
+    fn source(&self) -> u8 {
+        match *self {
+            Self::Token { source, .. } | Self::Join(source) | Self::Leave(source) => source,
+        }
+    }
+}
+
+/// the ring as this node sees it, shared between the transmit and receive halves of the driver
+pub(crate) struct TokenState {
+    config: TokenConfig,
+    id: u8,
+    ring: [Cell<u32>; 8],
+    holding: Cell<bool>,
+    /// this node announced itself and did not leave since
+    member: Cell<bool>,
+    held_frames: Cell<usize>,
+    last_activity: Cell<Instant>,
+    last_held: Cell<Instant>,
+    /// successor the token was passed to and until when it has to use it
+    passed_to: Cell<Option<(u8, Instant)>>,
+}
+
+impl TokenState {
+    pub fn new(config: TokenConfig, id: u8) -> Self {
+        let state = Self {
+            config,
+            id,
+            ring: Default::default(),
+            holding: Cell::new(false),
+            member: Cell::new(config.ring.contains(&id)),
+            held_frames: Cell::new(0),
+            last_activity: Cell::new(Instant::now()),
+            last_held: Cell::new(Instant::now()),
+            passed_to: Cell::new(None),
+        };
+        for &node in config.ring {
+            state.insert(node);
+        }
+        state
+    }
+
+    fn contains(&self, node: u8) -> bool {
+        self.ring[node as usize / 32].get() & 1 << (node % 32) != 0
+    }
+
+    fn insert(&self, node: u8) {
+        let word = &self.ring[node as usize / 32];
+        word.set(word.get() | 1 << (node % 32));
+    }
+
+    fn remove(&self, node: u8) {
+        let word = &self.ring[node as usize / 32];
+        word.set(word.get() & !(1 << (node % 32)));
+    }
+
+    /// the next node of the ring after this one, `None` if this node is alone
+    pub fn successor(&self) -> Option<u8> {
+        (1..=255u16)
+            .map(|step| (self.id as u16 + step) as u8)
+            .find(|&node| node != self.id && self.contains(node))
+    }
+
+    pub fn wants_membership(&self) -> bool {
+        match self.config.membership {
+            Some(membership) => membership.wants_membership(),
+            None => true,
+        }
+    }
+
+    pub fn is_member(&self) -> bool {
+        self.member.get()
+    }
+
+    pub fn is_holding(&self) -> bool {
+        self.holding.get()
+    }
+
+    /// the holder may send another frame
+    pub fn has_budget(&self) -> bool {
+        self.held_frames.get() < self.config.hold_budget
+    }
+
+    pub fn on_frame_sent(&self) {
+        self.held_frames.set(self.held_frames.get() + 1);
+    }
+
+    fn take(&self) {
+        self.holding.set(true);
+        self.held_frames.set(0);
+        self.last_held.set(Instant::now());
+    }
+
+    /// a node alone in the ring starts a new hold instead of passing the token to itself
+    pub fn restart_hold(&self) {
+        self.take();
+    }
+
+    /// when a token that was passed on has to be in use, `None` if it was not passed on
+    pub fn pass_deadline(&self) -> Option<(u8, Instant)> {
+        self.passed_to.get()
+    }
+
+    /// the successor did not use the token, it leaves the ring and the token comes back
+    pub fn on_pass_timeout(&self, successor: u8) {
+        info!("node {} did not take the token", successor);
+        self.remove(successor);
+        self.passed_to.set(None);
+        self.take();
+    }
+
+    /// when a lost token is regenerated, `None` if this node would not regenerate it
+    pub fn regeneration_deadline(&self) -> Option<Instant> {
+        if !self.member.get() || !self.wants_membership() {
+            return None;
+        }
+        let stagger = self.config.regeneration_step * self.id as u32;
+        Some(self.last_activity.get() + self.config.token_timeout + stagger)
+    }
+
+    pub fn regenerate(&self) {
+        info!("token lost, regenerating");
+        self.take();
+    }
+
+    /// a member that was not passed the token for a long time may have missed its join
+    pub fn needs_join(&self, now: Instant) -> bool {
+        if !self.wants_membership() {
+            return false;
+        }
+        !self.member.get() || now >= self.last_held.get() + self.config.rejoin_after
+    }
+
+    pub fn needs_leave(&self) -> bool {
+        self.member.get() && !self.wants_membership()
+    }
+
+    pub fn join_frame(&self) -> ControlFrame {
+        ControlFrame::Join(self.id)
+    }
+
+    pub fn leave_frame(&self) -> ControlFrame {
+        ControlFrame::Leave(self.id)
+    }
+
+    pub fn token_frame(&self, successor: u8) -> ControlFrame {
+        ControlFrame::Token {
+            destination: successor,
+            source: self.id,
+        }
+    }
+
+    pub fn on_join_sent(&self) {
+        self.member.set(true);
+        self.insert(self.id);
+        // the token has a full rejoin period to come by
+        self.last_held.set(Instant::now());
+    }
+
+    pub fn on_leave_sent(&self) {
+        self.member.set(false);
+        self.remove(self.id);
+    }
+
+    pub fn on_passed(&self, successor: u8) {
+        self.holding.set(false);
+        self.passed_to
+            .set(Some((successor, Instant::now() + self.config.pass_timeout)));
+    }
+
+    /// the holder is leaving and nobody is left to pass to
+    pub fn drop_token(&self) {
+        self.holding.set(false);
+    }
+
+    /// any burst read from the bus, `control` if it was a control frame
+    pub fn on_burst(&self, control: Option<ControlFrame>) {
+        self.last_activity.set(Instant::now());
+        let Some(control) = control else {
+            self.passed_to.set(None);
+            return;
+        };
+        let source = control.source();
+        if source == self.id {
+            return;
+        }
+        self.passed_to.set(None);
+        match control {
+            ControlFrame::Token { destination, .. } => {
+                self.insert(source);
+                if destination == self.id {
+                    self.take();
+                } else if self.holding.get() {
+                    info!("node {} passed a token as well, dropping ours", source);
+                    self.holding.set(false);
+                }
+            }
+            ControlFrame::Join(_) => self.insert(source),
+            ControlFrame::Leave(_) => self.remove(source),
+        }
+    }
+}
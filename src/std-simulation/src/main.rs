@@ -2,6 +2,7 @@
 #![feature(future_join)]
 #![feature(async_fn_in_trait)]
 #![feature(return_position_impl_trait_in_trait)]
+use communication::carrier_sense::LineActivity;
 use communication::{AsyncDevice, AsyncTimer};
 use communication::{Read, ReadError, Write, WriteError};
 use embassy_net::udp::UdpSocket;
@@ -9,6 +10,7 @@ use embassy_net::{Ipv4Address, PacketMetadata, Stack};
 use log::info;
 use std::future;
 use std::pin::Pin;
+use std::sync::Arc;
 use tokio::sync::broadcast;
 use tokio::time::{self, sleep, sleep_until, Duration, Instant};
 pub struct PanicAsyncTimer(Option<Pin<Box<time::Sleep>>>);
@@ -29,17 +31,20 @@ impl AsyncTimer for PanicAsyncTimer {
         return self.0.as_mut();
     }
 }
-pub struct InternalBusWriter(broadcast::Sender<Vec<u8>>);
+pub struct InternalBusWriter(broadcast::Sender<Vec<u8>>, Arc<LineActivity>);
 
 impl InternalBusWriter {
-    pub fn new(s: broadcast::Sender<Vec<u8>>) -> Self {
-        InternalBusWriter(s)
+    pub fn new(s: broadcast::Sender<Vec<u8>>, line: Arc<LineActivity>) -> Self {
+        InternalBusWriter(s, line)
     }
 }
 
 impl Write for InternalBusWriter {
     fn is_line_free(&self) -> bool {
-        return true;
+        return self.1.is_line_free();
+    }
+    fn time_until_free(&self) -> embassy_time::Duration {
+        self.1.time_until_free()
     }
     async fn write<'a>(&'a mut self, buf: &'a [u8]) -> Result<(), WriteError>
     where
@@ -56,11 +61,11 @@ impl Write for InternalBusWriter {
     }
 }
 
-pub struct InternalBusReader(broadcast::Receiver<Vec<u8>>);
+pub struct InternalBusReader(broadcast::Receiver<Vec<u8>>, Arc<LineActivity>);
 
 impl InternalBusReader {
-    pub fn new(b: broadcast::Receiver<Vec<u8>>) -> Self {
-        InternalBusReader(b)
+    pub fn new(b: broadcast::Receiver<Vec<u8>>, line: Arc<LineActivity>) -> Self {
+        InternalBusReader(b, line)
     }
 }
 
@@ -72,6 +77,8 @@ impl Read for InternalBusReader {
         let Ok(read) =  self.0.recv().await else {
                 return Err(ReadError::FramingError);
                };
+        // frames on the simulated bus arrive whole, so the line goes idle right away
+        self.1.on_rx_idle();
         let recv_len = read.len();
 
         if recv_len > buf.len() {
@@ -4,13 +4,20 @@ pub mod uart {
     use core::pin::Pin;
     use core::sync::atomic::{AtomicBool, Ordering};
 
-    use crate::stm32_uart::serial::{BasicUartRx, BasicUartTx};
+    use crate::stm32_uart::serial::{rx_busy, BasicUartRx, BasicUartTx};
+    use communication::carrier_sense::LineActivity;
     use communication::{Read, ReadError, Write, WriteError};
     use core::mem;
     use defmt::info;
     use embassy_futures::select::{select, Either};
     use embassy_stm32::usart::BasicInstance;
     use embassy_stm32::{self};
+    use embassy_time::{Duration, Timer};
+
+    /// largest number of echoed bytes compared at once
+    pub const MAX_VERIFY_WINDOW: usize = 64;
+    /// the echo of the last bytes is still on the line when the tx dma completes
+    const ECHO_TIMEOUT: Duration = Duration::from_millis(5);
 
     pub struct HalfDuplexUartRx<T, RxDma>
     where
@@ -19,6 +26,7 @@ pub mod uart {
     {
         ptr: *mut BasicUartRx<'static, T, RxDma>,
         stolen_signal: &'static AtomicBool,
+        line: &'static LineActivity,
     }
 
     impl<'d, T, RxDma> Read for HalfDuplexUartRx<T, RxDma>
@@ -36,6 +44,7 @@ pub mod uart {
         {
             self.stolen_signal.store(false, Ordering::SeqCst);
             let res = unsafe { Read::read_until_idle(&mut *self.ptr, buf).await };
+            self.line.on_rx_idle();
             if true == self.stolen_signal.load(Ordering::SeqCst) {
                 let () = core::future::pending().await;
             }
@@ -51,10 +60,12 @@ pub mod uart {
         pub(crate) fn new(
             rx: *mut BasicUartRx<'static, T, RxDma>,
             stolen_signal: &'static AtomicBool,
+            line: &'static LineActivity,
         ) -> Self {
             Self {
                 ptr: rx,
                 stolen_signal,
+                line,
             }
         }
     }
@@ -70,6 +81,8 @@ pub mod uart {
         tx: &'static mut BasicUartTx<'static, T, TxDma>,
         rx: *mut BasicUartRx<'static, T, RxDma>,
         rx_stolen_signal: &'static AtomicBool,
+        line: &'static LineActivity,
+        verify_window: usize,
     }
 
     impl<T, TxDma, RxDma> HalfDuplexUartTx<T, TxDma, RxDma>
@@ -84,6 +97,8 @@ pub mod uart {
             rx_dma: RxDma,
             tx_dma: TxDma,
             rx_stolen_signal: &'static AtomicBool,
+            line: &'static LineActivity,
+            verify_window: usize,
         ) -> Self {
             Self {
                 tx,
@@ -91,6 +106,8 @@ pub mod uart {
                 rx_dma,
                 tx_dma,
                 rx_stolen_signal,
+                line,
+                verify_window: verify_window.clamp(1, MAX_VERIFY_WINDOW),
             }
         }
 
@@ -99,53 +116,94 @@ pub mod uart {
             while self.rx_dma.is_running() {}
         }
 
-        fn collision_occurred(rx: &[u8; 5], tx: &[u8]) -> bool {
-            let min_len = min(rx.len(), tx.len());
-            for i in 0..min_len {
-                if rx[i] != tx[i] {
-                    return true;
+        /**
+         * compares the echo of the whole frame against what was sent, one window at a time.
+         * Each window is a separate dma read, so a smaller window notices a collision sooner
+         * but costs more interrupts per frame. The first `arbitration` bytes are read in one
+         * transfer of their own, a difference there means the arbitration was lost
+         */
+        async fn verify_echo(
+            rx: &mut BasicUartRx<'static, T, RxDma>,
+            sent: &[u8],
+            window: usize,
+            arbitration: usize,
+        ) -> Result<(), WriteError> {
+            let mut echo = [0; MAX_VERIFY_WINDOW];
+            let mut verified = 0;
+            while verified < sent.len() {
+                let in_arbitration = verified < arbitration;
+                let len = if in_arbitration {
+                    min(arbitration, MAX_VERIFY_WINDOW) - verified
+                } else {
+                    min(window, sent.len() - verified)
+                };
+                let read = match Read::read_until_idle(rx, &mut echo[..len]).await {
+                    Ok(read) => read,
+                    Err(err) => {
+                        info!("echo read failed after {} bytes: {:?}", verified, err);
+                        return Err(WriteError::CollisionError);
+                    }
+                };
+                if read == 0 || echo[..read] != sent[verified..verified + read] {
+                    info!("echo differs after {} bytes", verified);
+                    if in_arbitration {
+                        return Err(WriteError::ArbitrationLost);
+                    }
+                    return Err(WriteError::CollisionError);
                 }
+                verified += read;
             }
-            return false;
+            return Ok(());
         }
 
-        async unsafe fn duplex_transmit(&mut self, buffer: &[u8]) -> Result<(), WriteError> {
+        async unsafe fn duplex_transmit(
+            &mut self,
+            buffer: &[u8],
+            arbitration: usize,
+        ) -> Result<(), WriteError> {
             self.disable_rx();
             self.rx_stolen_signal.store(true, Ordering::SeqCst);
             let transmit_stolen = self.rx.as_mut().expect("cannot get rx pointer...");
-            let mut rx_buf: [u8; 5] = [0; 5];
-            let five_byte_read = Read::read_until_idle(transmit_stolen, &mut rx_buf);
+            let mut verify =
+                Self::verify_echo(transmit_stolen, buffer, self.verify_window, arbitration);
             let mut transmit = self.tx.write(buffer);
             let p_transmit = Pin::new_unchecked(&mut transmit);
-            let collision_result = select(p_transmit, five_byte_read).await;
-            let res: Result<(), WriteError> = match collision_result {
-                Either::First(_) => {
-                    info!("tx finished first? is board properly set up?");
-
-                    Err(WriteError::FramingError)
-                }
-                Either::Second(rx_res) => {
-                    info!("{:?}", &rx_res);
-                    if rx_res.is_err() || Self::collision_occurred(&rx_buf, buffer) {
-                        Err(WriteError::CollisionError)
-                    } else {
-                        Ok(())
-                    }
+            let p_verify = Pin::new_unchecked(&mut verify);
+            let (transmit_result, res) = match select(p_transmit, p_verify).await {
+                Either::First(transmit_result) => {
+                    // the echo of the last bytes is still arriving
+                    let p_verify = Pin::new_unchecked(&mut verify);
+                    let res = match select(p_verify, Timer::after(ECHO_TIMEOUT)).await {
+                        Either::First(res) => res,
+                        Either::Second(()) => {
+                            info!("echo incomplete, is board properly set up?");
+                            Err(WriteError::CollisionError)
+                        }
+                    };
+                    (Some(transmit_result), res)
                 }
+                Either::Second(res) => (None, res),
             };
             // being extra safe to not have ordering issues...
             self.rx_stolen_signal.store(true, Ordering::SeqCst);
+            // our own frame occupied the line as well
+            self.line.on_rx_idle();
 
             info!("RESULT: {:?}", &res);
             if res.is_err() {
-                // stop dma transfer
-                self.tx_dma.request_stop();
-                while self.tx_dma.is_running() {}
+                if transmit_result.is_none() {
+                    // stop dma transfer
+                    self.tx_dma.request_stop();
+                    while self.tx_dma.is_running() {}
+                }
                 return res;
             }
-            // do comparison, cancel dma if err
 
-            if let Err(e) = transmit.await {
+            let transmit_result = match transmit_result {
+                Some(transmit_result) => transmit_result,
+                None => transmit.await,
+            };
+            if let Err(e) = transmit_result {
                 info!("error in receipt: {}", &e);
                 return Err(WriteError::FramingError);
             }
@@ -161,13 +219,43 @@ pub mod uart {
         T: BasicInstance,
     {
         fn is_line_free(&self) -> bool {
-            return true; //todo improve
+            if rx_busy::<T>() {
+                self.line.on_rx_start();
+                return false;
+            }
+            self.line.is_line_free()
+        }
+        fn time_until_free(&self) -> Duration {
+            self.line.time_until_free()
         }
         async fn write<'a>(&'a mut self, buf: &'a [u8]) -> Result<(), WriteError>
         where
             Self: Sized,
         {
-            unsafe { self.duplex_transmit(buf).await }
+            unsafe { self.duplex_transmit(buf, 0).await }
+        }
+        async fn write_arbitrated<'a>(
+            &'a mut self,
+            buf: &'a [u8],
+            arbitration: usize,
+        ) -> Result<(), WriteError>
+        where
+            Self: Sized,
+        {
+            unsafe { self.duplex_transmit(buf, arbitration).await }
+        }
+        /// the echo of a jam is garbled by design, so nothing reads it back
+        async fn write_raw<'a>(&'a mut self, buf: &'a [u8]) -> Result<(), WriteError>
+        where
+            Self: Sized,
+        {
+            if let Err(e) = self.tx.write(buf).await {
+                info!("error in raw write: {}", &e);
+                return Err(WriteError::FramingError);
+            }
+            // our own burst occupied the line as well
+            self.line.on_rx_idle();
+            Ok(())
         }
     }
 
@@ -177,6 +265,8 @@ pub mod uart {
         rx_: &'static mut BasicUartRx<'static, T, RxDma>,
         tx_: &'static mut BasicUartTx<'static, T, TxDma>,
         taken_flag: &'static mut AtomicBool,
+        line: &'static LineActivity,
+        verify_window: usize,
         tx_dma: TxDma,
         rx_dma: RxDma,
     ) -> (
@@ -193,8 +283,16 @@ pub mod uart {
 
         let rx_mut_ptr_2: *mut BasicUartRx<T, RxDma> =
             unsafe { mem::transmute(rx_ as *const BasicUartRx<T, RxDma>) };
-        let tx_component = HalfDuplexUartTx::new(tx_, rx_mut_ptr, rx_dma, tx_dma, taken_flag);
-        let rx_component = HalfDuplexUartRx::new(rx_mut_ptr_2, taken_flag);
+        let tx_component = HalfDuplexUartTx::new(
+            tx_,
+            rx_mut_ptr,
+            rx_dma,
+            tx_dma,
+            taken_flag,
+            line,
+            verify_window,
+        );
+        let rx_component = HalfDuplexUartRx::new(rx_mut_ptr_2, taken_flag, line);
         return (rx_component, tx_component);
     }
 }
@@ -6,6 +6,7 @@ pub mod init {
 
     use crate::locator::locator;
     use crate::stm32_uart::serial::{BasicUartRx, BasicUartTx};
+    use communication::carrier_sense::LineActivity;
     use communication::CoreServiceLocator;
     use embassy_stm32::gpio::Output;
     use embassy_stm32::pac::RCC;
@@ -18,6 +19,7 @@ pub mod init {
     use embassy_stm32::time::Hertz;
     use embassy_stm32::usart::{Config as UartConfig, Uart, UartRx, UartTx};
     use embassy_stm32::{interrupt, Config, Peripheral};
+    use embassy_time::Duration;
     use static_cell::StaticCell;
     use {defmt_rtt as _, panic_probe as _};
 
@@ -29,6 +31,10 @@ pub mod init {
         }};
     }
     const MSI_RANGE: MSIRange = MSIRange::Range7; // 8 MHz;
+    // ~5 characters at 500 kbaud
+    const INTER_FRAME_GAP: Duration = Duration::from_micros(100);
+    /// echoed bytes compared per dma read while transmitting
+    const COLLISION_VERIFY_WINDOW: usize = 16;
 
     impl ToPLL for ClockSrc {
         fn to_pll_selection(&self) -> u8 {
@@ -167,8 +173,16 @@ pub mod init {
         let u3tx = UART3_A.init_with(|| u3tx.into());
         let u3rx = UART3_B.init_with(|| u3rx.into());
         let uart3_take_flag = singleton!(AtomicBool::new(false));
-        let (half_duplex_uart_3_rx, half_duplex_uart_3_tx) =
-            half_duplex::uart::new(u3rx, u3tx, uart3_take_flag, u3_tx_dma, u3_rx_dma);
+        let uart3_line = singleton!(LineActivity::new(INTER_FRAME_GAP));
+        let (half_duplex_uart_3_rx, half_duplex_uart_3_tx) = half_duplex::uart::new(
+            u3rx,
+            u3tx,
+            uart3_take_flag,
+            uart3_line,
+            COLLISION_VERIFY_WINDOW,
+            u3_tx_dma,
+            u3_rx_dma,
+        );
 
         let irq_usart2 = interrupt::take!(USART2);
         let mut config_usart2: UartConfig = Default::default();
@@ -196,8 +210,16 @@ pub mod init {
             (a.into(), b.into())
         });
         let usart2_take_flag = singleton!(AtomicBool::new(false));
-        let (half_duplex_uart_2_rx, half_duplex_uart_2_tx) =
-            half_duplex::uart::new(u2rx, u2tx, usart2_take_flag, u2_tx_dma, u2_rx_dma);
+        let usart2_line = singleton!(LineActivity::new(INTER_FRAME_GAP));
+        let (half_duplex_uart_2_rx, half_duplex_uart_2_tx) = half_duplex::uart::new(
+            u2rx,
+            u2tx,
+            usart2_take_flag,
+            usart2_line,
+            COLLISION_VERIFY_WINDOW,
+            u2_tx_dma,
+            u2_rx_dma,
+        );
 
         let timer = AsyncBasicTimer::new(peripherals.TIM6, interrupt::take!(TIM6), Hertz::mhz(1));
         let timer2 = AsyncBasicTimer::new(peripherals.TIM7, interrupt::take!(TIM7), Hertz::mhz(1));
@@ -2,9 +2,14 @@ pub mod service {
 
     use crate::locator::locator::{HardwareLocator, Locator};
 
-    use communication::half_duplex::{AsyncHalfDuplexUart, CommunicationState};
+    use communication::address_filter::AddressFilter;
+    use communication::backoff::BinaryExponential;
+    use communication::compression::CompressionConfig;
+    use communication::half_duplex::{AsyncHalfDuplexUart, CommunicationState, HalfDuplexConfig};
+    use communication::medium::{link_local_ipv6, node_mac, LinkMedium};
     use communication::AsyncDevice;
     use communication::CoreServiceLocator;
+    use defmt::info;
     use embassy_net::{ConfigStrategy, Ipv4Address, Ipv4Cidr, Stack, StackResources};
     use embassy_net_driver::Driver;
 
@@ -25,8 +30,11 @@ pub mod service {
             &mut self,
         ) -> Option<(&'static mut Stack<impl Driver>, impl AsyncDevice)> {
             const IP_ADDRESS_ONE: Ipv4Cidr = Ipv4Cidr::new(Ipv4Address::new(192, 168, 69, 3), 24);
-            const MAC_ADDRESS_ONE: [u8; 6] = [0, 2, 3, 4, 5, 7];
-            let state = singleton!(CommunicationState::new());
+            const MAC_ADDRESS_ONE: [u8; 6] = node_mac(3);
+            // the stack only takes a static ipv4 config, the bus filter and the medium derive
+            // the ipv6 side from the mac
+            const LINK_LOCAL_ONE: [u8; 16] = link_local_ipv6(MAC_ADDRESS_ONE);
+            let state = singleton!(<CommunicationState>::new());
             let (runner, device) = embassy_net_driver_channel::new(state, MAC_ADDRESS_ONE);
             let usart2_tx = self.tx_channel_one()?;
             let usart2_rx = self.rx_channel_one()?;
@@ -36,7 +44,22 @@ pub mod service {
             rng.try_fill_bytes(&mut seed).ok()?;
             let seed = u64::from_le_bytes(seed);
 
-            let uart_driver = AsyncHalfDuplexUart::new(usart2_rx, usart2_tx, tim6, runner, rng);
+            let mut link_config = HalfDuplexConfig::default();
+            link_config.address = MAC_ADDRESS_ONE;
+            link_config.medium = LinkMedium::Node;
+            link_config.compression = Some(CompressionConfig::default());
+            link_config.address_filter = Some(AddressFilter::default());
+            // with separate tx and rx lines `<AsyncFullDuplexUart<_, _>>::new` takes the
+            // same arguments
+            let uart_driver = <AsyncHalfDuplexUart<_, _, _, _, _>>::new(
+                usart2_rx,
+                usart2_tx,
+                tim6,
+                runner,
+                rng,
+                BinaryExponential::default(),
+                link_config,
+            );
             let config = ConfigStrategy::Static(embassy_net::Config {
                 address: IP_ADDRESS_ONE,
                 dns_servers: Vec::new(),
@@ -49,6 +72,7 @@ pub mod service {
                 singleton!(StackResources::<1, 2, 8>::new()),
                 seed
             ));
+            info!("stack one link local address {:x}", LINK_LOCAL_ONE);
             Some((stack, uart_driver))
         }
 
@@ -56,8 +80,9 @@ pub mod service {
             &mut self,
         ) -> Option<(&'static mut Stack<impl Driver>, impl AsyncDevice)> {
             const IP_ADDRESS_TWO: Ipv4Cidr = Ipv4Cidr::new(Ipv4Address::new(192, 168, 69, 2), 24);
-            const MAC_ADDRESS_TWO: [u8; 6] = [0, 2, 3, 4, 5, 6];
-            let state = singleton!(CommunicationState::new());
+            const MAC_ADDRESS_TWO: [u8; 6] = node_mac(2);
+            const LINK_LOCAL_TWO: [u8; 16] = link_local_ipv6(MAC_ADDRESS_TWO);
+            let state = singleton!(<CommunicationState>::new());
             let (runner, device) = embassy_net_driver_channel::new(state, MAC_ADDRESS_TWO);
             let usart3_tx = self.tx_channel_two()?;
             let usart3_rx = self.rx_channel_two()?;
@@ -67,7 +92,20 @@ pub mod service {
             rng.try_fill_bytes(&mut seed).ok()?;
             let seed = u64::from_le_bytes(seed);
 
-            let uart_driver = AsyncHalfDuplexUart::new(usart3_rx, usart3_tx, tim7, runner, rng);
+            let mut link_config = HalfDuplexConfig::default();
+            link_config.address = MAC_ADDRESS_TWO;
+            link_config.medium = LinkMedium::Node;
+            link_config.compression = Some(CompressionConfig::default());
+            link_config.address_filter = Some(AddressFilter::default());
+            let uart_driver = <AsyncHalfDuplexUart<_, _, _, _, _>>::new(
+                usart3_rx,
+                usart3_tx,
+                tim7,
+                runner,
+                rng,
+                BinaryExponential::default(),
+                link_config,
+            );
             let config = ConfigStrategy::Static(embassy_net::Config {
                 address: IP_ADDRESS_TWO,
                 dns_servers: Vec::new(),
@@ -80,6 +118,7 @@ pub mod service {
                 singleton!(StackResources::<1, 2, 8>::new()),
                 seed
             ));
+            info!("stack two link local address {:x}", LINK_LOCAL_TWO);
             Some((stack, uart_driver))
         }
     }
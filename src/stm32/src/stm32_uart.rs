@@ -2,6 +2,12 @@ pub mod serial {
     use communication::{Read, ReadError, Write, WriteError};
     use defmt::*;
     use embassy_stm32::usart::{BasicInstance, UartRx, UartTx};
+
+    /// the peripheral sets BUSY as soon as it sees a start bit and clears it once the line is idle
+    pub fn rx_busy<T: BasicInstance>() -> bool {
+        unsafe { T::regs().isr().read().busy() }
+    }
+
     pub struct BasicUartRx<'d, T, RxDma>(UartRx<'d, T, RxDma>)
     where
         T: BasicInstance,
@@ -46,7 +52,7 @@ pub mod serial {
             }
         }
         fn is_line_free(&self) -> bool {
-            true
+            !rx_busy::<T>()
         }
     }
 